@@ -1,17 +1,331 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 
-use super::Client;
+use super::product::{nullable, Product};
+use super::response_groups::join;
+use super::{take_field, Client};
 use crate::Result;
 
+pub use super::response_groups::CatalogResponseGroup;
+
+/// Most products `get_products` returns per page
+pub const MAX_NUM_RESULTS: u32 = 50;
+/// Most reviews returned with each product
+pub const MAX_REVIEWS_NUM_RESULTS: u32 = 10;
+
+/// `products_sort_by`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    Relevance,
+    BestSellers,
+    AvgRating,
+    Title,
+    #[serde(rename = "-Title")]
+    TitleDesc,
+    ReleaseDate,
+    #[serde(rename = "-ReleaseDate")]
+    ReleaseDateDesc,
+    RuntimeLength,
+    #[serde(rename = "-RuntimeLength")]
+    RuntimeLengthDesc,
+    ContentLevel,
+    #[serde(rename = "-ContentLevel")]
+    ContentLevelDesc,
+    ProductSiteLaunchDate,
+    AmazonEnglish,
+}
+
+/// A catalog plan, e.g. the titles included with a membership
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogPlan {
+    Enterprise,
+    RodizioFreeBasic,
+    AyceRomance,
+    AllYouCanEat,
+    AmazonEnglish,
+    ComplimentaryOriginalMemberBenefit,
+    Radio,
+    SpecialBenefit,
+    Rodizio,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewsSortBy {
+    MostHelpful,
+    MostRecent,
+}
+
+/// `similarity_type` of [`Client::get_similar_products`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimilarityType {
+    InTheSameSeries,
+    NextInSameSeries,
+    ByTheSameAuthor,
+    ByTheSameNarrator,
+    RawSimilarities,
+}
+
+/// `root` of [`Client::get_catalog_categories`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CategoryRoot {
+    Genres,
+    Curated,
+    EditorsPicks,
+    ExploreBy,
+    ClientContent,
+    AmazonEnglishProducts,
+    AEReadster,
+    ChannelsConfigurator,
+    InstitutionsHpMarketing,
+    RodizioBuckets,
+    RodizioGenres,
+    RodizioEpisodesAndSeries,
+    Shorts,
+    ShortsCurated,
+    ShortsPrime,
+    ShortsSandbox,
+    ShortsIntroOutroRemoval,
+}
+
+/// A catalog category, with as many levels of children as were requested
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    #[serde(default, deserialize_with = "nullable")]
+    pub children: Vec<Category>,
+}
+
+/// Serialize an enum variant to the string the API expects
+pub(crate) fn query_value<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
+
+/// Query of [`Client::get_products`], limits are validated as they are set
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogSearch {
+    params: Map<String, Value>,
+    num_results: u32,
+    page: u32,
+}
+
+impl Default for CatalogSearch {
+    fn default() -> Self {
+        Self {
+            params: Map::new(),
+            num_results: MAX_NUM_RESULTS,
+            page: 1,
+        }
+    }
+}
+
+impl CatalogSearch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.params.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn keywords(self, keywords: &str) -> Self {
+        self.set("keywords", keywords)
+    }
+
+    pub fn title(self, title: &str) -> Self {
+        self.set("title", title)
+    }
+
+    pub fn author(self, author: &str) -> Self {
+        self.set("author", author)
+    }
+
+    pub fn narrator(self, narrator: &str) -> Self {
+        self.set("narrator", narrator)
+    }
+
+    pub fn publisher(self, publisher: &str) -> Self {
+        self.set("publisher", publisher)
+    }
+
+    /// Products in all of the categories
+    pub fn categories(self, category_ids: &[u64]) -> Self {
+        let ids: Vec<String> = category_ids.iter().map(u64::to_string).collect();
+        self.set("category_id", ids.join(","))
+    }
+
+    pub fn plan(self, plan: CatalogPlan) -> Self {
+        self.set("plan", query_value(&plan))
+    }
+
+    pub fn sort_by(self, sort_by: SortBy) -> Self {
+        self.set("products_sort_by", query_value(&sort_by))
+    }
+
+    pub fn response_groups(self, groups: &[CatalogResponseGroup]) -> Self {
+        self.set("response_groups", join(groups))
+    }
+
+    /// Products released since the timestamp, [RFC3339](https://tools.ietf.org/html/rfc3339)
+    pub fn since(self, timestamp: &str) -> Self {
+        self.set("products_since_timestamp", timestamp)
+    }
+
+    pub fn image_sizes(self, sizes: &[u32]) -> Self {
+        let sizes: Vec<String> = sizes.iter().map(u32::to_string).collect();
+        self.set("image_sizes", sizes.join(","))
+    }
+
+    /// Results per page, 1 to 50
+    pub fn num_results(mut self, num_results: u32) -> Result<Self> {
+        if !(1..=MAX_NUM_RESULTS).contains(&num_results) {
+            return Err(format!(
+                "num_results must be 1 to {}: {}",
+                MAX_NUM_RESULTS, num_results
+            )
+            .into());
+        }
+        self.num_results = num_results;
+        Ok(self)
+    }
+
+    /// Page of results, starting at 1
+    pub fn page(mut self, page: u32) -> Result<Self> {
+        if page == 0 {
+            return Err("Pages start at 1".into());
+        }
+        self.page = page;
+        Ok(self)
+    }
+
+    /// Reviews included with each product, 0 to 10
+    pub fn reviews(self, num_results: u32, sort_by: ReviewsSortBy) -> Result<Self> {
+        if num_results > MAX_REVIEWS_NUM_RESULTS {
+            return Err(format!(
+                "reviews_num_results must be 0 to {}: {}",
+                MAX_REVIEWS_NUM_RESULTS, num_results
+            )
+            .into());
+        }
+        Ok(self
+            .set("reviews_num_results", num_results)
+            .set("reviews_sort_by", query_value(&sort_by)))
+    }
+
+    pub fn to_params(&self) -> Value {
+        let mut params = self.params.clone();
+        params.insert("num_results".into(), json!(self.num_results));
+        params.insert("page".into(), json!(self.page));
+        Value::Object(params)
+    }
+}
+
+/// A page of catalog search results
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SearchResults {
+    #[serde(default, deserialize_with = "nullable")]
+    pub products: Vec<Product>,
+    pub total_results: Option<u64>,
+}
+
 impl Client {
-    /// GET /1.0/collections
+    /// Typed page of [`Client::get_products`] results
+    pub async fn search_catalog(&self, search: &CatalogSearch) -> Result<SearchResults> {
+        let json = self.get_products(Some(search.to_params())).await?;
+        if let Some(message) = json["message"].as_str() {
+            return Err(message.into());
+        }
+        Ok(serde_json::from_value(json)?)
+    }
+
+    /// Every product of the search, from its page on, stopping after `max_results`
+    pub async fn search_catalog_all(
+        &self,
+        search: &CatalogSearch,
+        max_results: usize,
+    ) -> Result<Vec<Product>> {
+        let mut search = search.clone();
+        let mut products = Vec::new();
+        while products.len() < max_results {
+            let results = self.search_catalog(&search).await?;
+            let len = results.products.len();
+            products.extend(results.products);
+            let total = results.total_results.unwrap_or(u64::MAX) as usize;
+            if len < search.num_results as usize || products.len() >= total {
+                break;
+            }
+            search.page += 1;
+        }
+        products.truncate(max_results);
+        Ok(products)
+    }
+
+    /// Typed version of [`Client::get_products_by_asin`]
+    pub async fn get_product(&self, asin: &str, params: Option<Value>) -> Result<Product> {
+        let json = self.get_products_by_asin(asin, params).await?;
+        take_field(json, "product")
+    }
+
+    /// Typed version of [`Client::get_similar_products`], with as many products as one page holds
+    pub async fn get_similar(
+        &self,
+        asin: &str,
+        similarity_type: SimilarityType,
+        response_groups: &[CatalogResponseGroup],
+    ) -> Result<Vec<Product>> {
+        let params = json! {{
+            "similarity_type": query_value(&similarity_type),
+            "response_groups": join(response_groups),
+            "num_results": MAX_NUM_RESULTS,
+        }};
+        let json = self.get_similar_products(asin, Some(params)).await?;
+        take_field(json, "similar_products")
+    }
+
+    /// Typed version of [`Client::get_catalog_categories`], the categories under `root` down to
+    /// `num_levels` levels
+    pub async fn get_categories(
+        &self,
+        root: CategoryRoot,
+        num_levels: u32,
+    ) -> Result<Vec<Category>> {
+        let params = json! {{
+            "root": query_value(&root),
+            "categories_num_levels": num_levels.max(1),
+        }};
+        let json = self.get_catalog_categories(Some(params)).await?;
+        take_field(json, "categories")
+    }
+
+    /// Typed version of [`Client::get_catalog_category_by_id`], with its direct children
+    pub async fn get_category(&self, category_id: &str) -> Result<Category> {
+        let json = self.get_catalog_category_by_id(category_id, None).await?;
+        take_field(json, "category")
+    }
+
+    /// GET /1.0/catalog/categories
     ///
     /// Query Parameters:
-    /// - `state_token` (string) – [ey…]
-    /// - `visibility_types` (string) – [Private, Discoverable]
-    pub async fn get_collections(&self, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/collections", self.base_url);
+    /// - `response_groups` (string) – [category_metadata, products]
+    /// - `products_plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, US Minerva, Universal, AmazonEnglish,
+    ///   ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
+    /// - `products_in_plan_timestamp` (string)
+    /// - `products_num_results` (integer)
+    /// - `runtime_length_min` (integer)
+    /// - `content_level` (string)
+    /// - `content_type` (string)
+    /// - `categories_num_levels` (integer) – (greater than or equal to 1)
+    /// - `ids` (string) – \d+(,\d+)*
+    /// - `root` (string) – [InstitutionsHpMarketing, ChannelsConfigurator, AEReadster, ShortsPrime, ExploreBy, RodizioBuckets, EditorsPicks,
+    ///   ClientContent, RodizioGenres, AmazonEnglishProducts, ShortsSandbox, Genres, Curated, ShortsIntroOutroRemoval, Shorts,
+    ///   RodizioEpisodesAndSeries, ShortsCurated]
+    pub async fn get_catalog_categories(&self, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/catalog/categories", self.base_url);
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -24,25 +338,31 @@ impl Client {
         Ok(json)
     }
 
-    /// POST /1.0/collections
-    /// Create a new collection
+    /// GET /1.0/catalog/categories/(category_id)
     ///
-    /// Request JSON Object:
-    /// - `name` (string)
-    /// - `asins` (array of strings) – []
-    /// - `description` (string)
+    /// Parameters:
+    /// - `category_id` (string)
     ///
-    /// Response JSON Object:
-    /// - `collection_id` (string)
-    /// - `creation_date` (string)
-    /// - `customer_id` (string)
-    /// - `marketplace` (string)
-    pub async fn post_collections(&self, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/collections", self.base_url);
-
-        let mut req = self.client.post(url);
+    /// Query Parameters:
+    /// - `image_dpi` (integer)
+    /// - `image_sizes` (string)
+    /// - `image_variants` (string)
+    /// - `products_in_plan_timestamp` (string)
+    /// - `products_num_results` (integer)
+    /// - `products_plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, AmazonEnglish, ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
+    /// - `products_sort_by` (string) – [-ReleaseDate, ContentLevel, -Title, AmazonEnglish, AvgRating, BestSellers, -RuntimeLength, ReleaseDate, ProductSiteLaunchDate, -ContentLevel, Title, Relevance, RuntimeLength]
+    /// - `reviews_num_results` (integer)
+    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
+    pub async fn get_catalog_category_by_id(
+        &self,
+        category_id: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!("{}/1.0/catalog/categories/{}", self.base_url, category_id);
+
+        let mut req = self.client.get(url);
         if let Some(params) = params {
-            req = req.json(&params);
+            req = req.query(&params);
         }
         let req = req.build()?;
 
@@ -51,16 +371,32 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/collections/(collection_id)
+    /// GET /1.0/catalog/products
     ///
-    /// Parameters:
-    /// - `collection_id` (string)
-    pub async fn get_collection_by_id(
-        &self,
-        collection_id: &str,
-        params: Option<Value>,
-    ) -> Result<Value> {
-        let url = format!("{}/1.0/collections/{}", self.base_url, collection_id);
+    /// Query Parameters:
+    /// - `author` (string)
+    /// - `browse_type` (string)
+    /// - `category_id` (integer) – \d+(,\d+)*
+    /// - `disjunctive_category_ids` (string)
+    /// - `image_dpi` (integer)
+    /// - `image_sizes` (string)
+    /// - `in_plan_timestamp` (string)
+    /// - `keywords` (string)
+    /// - `narrator` (string)
+    /// - `not_in_plan_timestamp` (string)
+    /// - `num_most_recent` (integer)
+    /// - `num_results` (integer) – (max: 50)
+    /// - `page` (integer)
+    /// - `plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, AmazonEnglish, ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
+    /// - `products_since_timestamp` (string)
+    /// - `products_sort_by` (string) – [-ReleaseDate, ContentLevel, -Title, AmazonEnglish, AvgRating, BestSellers, -RuntimeLength, ReleaseDate, ProductSiteLaunchDate, -ContentLevel, Title, Relevance, RuntimeLength]
+    /// - `publisher` (string)
+    /// - `response_groups` (string) – [contributors, media, price, product_attrs, product_desc, product_extended_attrs, product_plan_details, product_plans, rating, review_attrs, reviews, sample, series, sku]
+    /// - `reviews_num_results` (integer) – (max: 10)
+    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
+    /// - `title` (string)
+    pub async fn get_products(&self, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/catalog/products", self.base_url);
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -73,33 +409,26 @@ impl Client {
         Ok(json)
     }
 
-    /// PUT /1.0/collections/(collection_id)
-    /// Modify a collection
+    /// GET /1.0/catalog/products/(string:asin)
     ///
     /// Parameters:
-    /// - `collection_id` (string)
-    ///
-    /// Request JSON Object:
-    /// - `state_token` (string)
-    /// - `collection_id` (string)
-    /// - `name` (string)
-    /// - `description` (string)
+    /// - `asin` (string) – The ASIN of the book
     ///
-    /// Response JSON Object:
-    /// - `state_token` (string)
-    /// - `collection_id` (string)
-    /// - `name` (string)
-    /// - `description` (string)
-    pub async fn put_collection_by_id(
-        &self,
-        collection_id: &str,
-        params: Option<Value>,
-    ) -> Result<Value> {
-        let url = format!("{}/1.0/collections/{}", self.base_url, collection_id);
+    /// Query Parameters:
+    /// - `image_dpi` (integer)
+    /// - `image_sizes` (string)
+    /// - `response_groups` (string) – [contributors, media, price, product_attrs, product_desc, product_details, product_extended_attrs,
+    ///   product_plan_details, product_plans, rating, sample, sku, series, reviews, relationships, review_attrs, category_ladders,
+    ///   claim_code_url, provided_review, rights, customer_rights]
+    /// - `reviews_num_results` (integer) – \d+ (max: 10)
+    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
+    /// - `asins` (string)
+    pub async fn get_products_by_asin(&self, asin: &str, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/catalog/products/{}", self.base_url, asin);
 
-        let mut req = self.client.put(url);
+        let mut req = self.client.get(url);
         if let Some(params) = params {
-            req = req.json(&params);
+            req = req.query(&params);
         }
         let req = req.build()?;
 
@@ -108,19 +437,17 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/collections/(collection_id)/items
+    /// GET /1.0/catalog/products/(string:asin)/reviews
     ///
     /// Parameters:
-    /// - `collection_id` (string) – e.g. __FAVORITES
+    /// - `asin` (string) – The ASIN of the book
     ///
     /// Query Parameters:
-    /// - `response_groups` (string) – [always-returned]
-    pub async fn get_items_by_collection_id(
-        &self,
-        collection_id: &str,
-        params: Option<Value>,
-    ) -> Result<Value> {
-        let url = format!("{}/1.0/collections/{}/items", self.base_url, collection_id);
+    /// - `sort_by` (string) – [MostHelpful, MostRecent]
+    /// - `num_results` (integer) – (max: 50)
+    /// - `page` (integer)
+    pub async fn get_product_reviews(&self, asin: &str, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/catalog/products/{}/reviews", self.base_url, asin);
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -133,31 +460,30 @@ impl Client {
         Ok(json)
     }
 
-    /// POST /1.0/collections/(collection_id)/items
-    /// Add item(s) to a collection
+    /// GET /1.0/catalog/products/(string:asin)/sims
     ///
     /// Parameters:
-    /// - `collection_id` (string)
+    /// - `asin` (string) – The ASIN of the book
     ///
-    /// Request JSON Object:
-    /// - `collection_id` (string)
-    /// - `asins` (array of strings) – []
-    ///
-    /// Response JSON Object:
-    /// - `description` (string)
-    /// - `name` (string)
-    /// - `num_items_added` (integer)
-    /// - `state_token` (string)
-    pub async fn post_items_by_collection_id(
-        &self,
-        collection_id: &str,
-        params: Option<Value>,
-    ) -> Result<Value> {
-        let url = format!("{}/1.0/collections/{}/items", self.base_url, collection_id);
+    /// Query Parameters:
+    /// - `category_image_variants` (string)
+    /// - `image_dpi` (integer)
+    /// - `image_sizes` (string)
+    /// - `in_plan_timestamp` (string)
+    /// - `language` (string)
+    /// - `not_in_plan_timestamp` (string)
+    /// - `num_results` (integer) – (max: 50)
+    /// - `plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, AmazonEnglish, ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
+    /// - `response_groups` (string) – [contributors, media, price, product_attrs, product_desc, product_extended_attrs, product_plans, rating, review_attrs, reviews, sample, sku]
+    /// - `reviews_num_results` (integer) – (max: 10)
+    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
+    /// - `similarity_type` (string) – [InTheSameSeries, ByTheSameNarrator, RawSimilarities, ByTheSameAuthor, NextInSameSeries]
+    pub async fn get_similar_products(&self, asin: &str, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/catalog/products/{}/sims", self.base_url, asin);
 
-        let mut req = self.client.post(url);
+        let mut req = self.client.get(url);
         if let Some(params) = params {
-            req = req.json(&params);
+            req = req.query(&params);
         }
         let req = req.build()?;
 
@@ -166,3 +492,42 @@ impl Client {
         Ok(json)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_search() {
+        let search = CatalogSearch::new()
+            .author("Brandon Sanderson")
+            .sort_by(SortBy::ReleaseDateDesc)
+            .plan(CatalogPlan::AyceRomance)
+            .response_groups(&[
+                CatalogResponseGroup::Contributors,
+                CatalogResponseGroup::ProductPlanDetails,
+            ])
+            .categories(&[18580606011, 18580607011])
+            .num_results(20)
+            .unwrap();
+        assert_eq!(
+            search.to_params(),
+            json!({
+                "author": "Brandon Sanderson",
+                "products_sort_by": "-ReleaseDate",
+                "plan": "AyceRomance",
+                "response_groups": "contributors,product_plan_details",
+                "category_id": "18580606011,18580607011",
+                "num_results": 20,
+                "page": 1,
+            })
+        );
+
+        assert!(CatalogSearch::new().num_results(51).is_err());
+        assert!(CatalogSearch::new().num_results(0).is_err());
+        assert!(CatalogSearch::new().page(0).is_err());
+        assert!(CatalogSearch::new()
+            .reviews(11, ReviewsSortBy::MostHelpful)
+            .is_err());
+    }
+}
@@ -1,28 +1,331 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use super::Client;
+use super::product::nullable;
+use super::{json_or_null, take_field, Client};
 use crate::Result;
 
+/// Attempts of a write whose `state_token` keeps going stale
+const MAX_STATE_ATTEMPTS: usize = 3;
+/// Collections or items requested per page
+const PAGE_SIZE: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Private,
+    Discoverable,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Private => "Private",
+            Visibility::Discoverable => "Discoverable",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Collection {
+    pub collection_id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Token of the current version, required by writes
+    pub state_token: Option<String>,
+    #[serde(alias = "visibility_type")]
+    pub visibility: Option<Visibility>,
+    pub creation_date: Option<String>,
+    /// Asins of the first items, when included in the response
+    #[serde(default, deserialize_with = "nullable")]
+    pub preview_asins: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CollectionItem {
+    pub asin: String,
+    pub added_date: Option<String>,
+}
+
+/// Whether the write was rejected because the `state_token` was out of date
+fn is_stale_state(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED
+    )
+}
+
+fn check_message(json: Value) -> Result<Value> {
+    match json["message"].as_str() {
+        Some(message) => Err(message.into()),
+        None => Ok(json),
+    }
+}
+
+fn check_status(status: StatusCode, json: Value) -> Result<Value> {
+    let json = check_message(json)?;
+    match status.is_success() {
+        true => Ok(json),
+        false => Err(format!("Request failed: {}", status).into()),
+    }
+}
+
+/// Run `write` with the `current` state, getting it again and retrying while the
+/// `state_token` went stale in between
+async fn retry_stale<T>(
+    mut current: impl AsyncFnMut() -> Result<T>,
+    write: impl AsyncFn(T) -> Result<(StatusCode, Value)>,
+) -> Result<Value> {
+    let mut attempt = 1;
+    loop {
+        let (status, json) = write(current().await?).await?;
+        if !is_stale_state(status) || attempt == MAX_STATE_ATTEMPTS {
+            return check_status(status, json);
+        }
+        attempt += 1;
+    }
+}
+
+/// The asins of `items` in the order of `asins`, followed by the rest in their current order
+fn reorder<'a>(items: &'a [CollectionItem], asins: &[&str]) -> Vec<&'a str> {
+    let mut order: Vec<&str> = asins
+        .iter()
+        .filter_map(|asin| items.iter().find(|i| i.asin == *asin))
+        .map(|i| i.asin.as_str())
+        .collect();
+    order.extend(
+        items
+            .iter()
+            .map(|i| i.asin.as_str())
+            .filter(|asin| !asins.contains(asin)),
+    );
+    order
+}
+
+/// Every page of `field`, following `continuation_token` until it is missing or repeated
+async fn all_pages<T: DeserializeOwned>(
+    field: &str,
+    mut fetch: impl AsyncFnMut(Option<&str>) -> Result<Value>,
+) -> Result<Vec<T>> {
+    let mut items = Vec::new();
+    let mut continuation_token: Option<String> = None;
+    loop {
+        let mut json = fetch(continuation_token.as_deref()).await?;
+        let token = json["continuation_token"].as_str().map(String::from);
+        let page: Vec<T> = take_field(json.take(), field)?;
+        let len = page.len();
+        items.extend(page);
+        match token {
+            Some(token) if len > 0 && continuation_token.as_ref() != Some(&token) => {
+                continuation_token = Some(token)
+            }
+            _ => break,
+        }
+    }
+    Ok(items)
+}
+
 impl Client {
-    /// GET /1.0/catalog/categories
+    /// Collections of the user, of every visibility when `visibility` is empty
+    pub async fn list_collections(&self, visibility: &[Visibility]) -> Result<Vec<Collection>> {
+        let mut params = json!({ "page_size": PAGE_SIZE });
+        if !visibility.is_empty() {
+            let types: Vec<&str> = visibility.iter().map(Visibility::as_str).collect();
+            params["visibility_types"] = json!(types.join(","));
+        }
+        all_pages("collections", async |continuation_token| {
+            let mut params = params.clone();
+            if let Some(token) = continuation_token {
+                params["continuation_token"] = json!(token);
+            }
+            self.get_collections(Some(params)).await
+        })
+        .await
+    }
+
+    pub async fn get_collection(&self, collection_id: &str) -> Result<Collection> {
+        let json = self.get_collection_by_id(collection_id, None).await?;
+        let json = check_message(json)?;
+        Ok(serde_json::from_value(json)?)
+    }
+
+    pub async fn create_collection(
+        &self,
+        name: &str,
+        description: Option<&str>,
+        asins: &[&str],
+    ) -> Result<Collection> {
+        let params = json! {{
+            "name": name,
+            "description": description.unwrap_or_default(),
+            "asins": asins,
+        }};
+        let json = check_message(self.post_collections(Some(params)).await?)?;
+        let mut collection: Collection = serde_json::from_value(json)?;
+        collection.name.get_or_insert_with(|| name.to_string());
+        if collection.description.is_none() {
+            collection.description = description.map(String::from);
+        }
+        Ok(collection)
+    }
+
+    pub async fn rename_collection(&self, collection_id: &str, name: &str) -> Result<Collection> {
+        self.update_collection(collection_id, Some(name), None)
+            .await
+    }
+
+    pub async fn describe_collection(
+        &self,
+        collection_id: &str,
+        description: &str,
+    ) -> Result<Collection> {
+        self.update_collection(collection_id, None, Some(description))
+            .await
+    }
+
+    /// Change the name and/or description, keeping whatever is `None`
+    pub async fn update_collection(
+        &self,
+        collection_id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<Collection> {
+        let json = self
+            .with_state_token(collection_id, async |current: Collection| {
+                let params = json! {{
+                    "state_token": current.state_token,
+                    "collection_id": collection_id,
+                    "name": name.map(String::from).or(current.name),
+                    "description": description.map(String::from).or(current.description),
+                }};
+                self.write_collection(Method::PUT, collection_id, "", params)
+                    .await
+            })
+            .await?;
+        Ok(serde_json::from_value(json)?)
+    }
+
+    pub async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        let json = self.delete_collection_by_id(collection_id, None).await?;
+        check_message(json)?;
+        Ok(())
+    }
+
+    /// Asins of the collection, in the collection's order
+    pub async fn get_collection_items(&self, collection_id: &str) -> Result<Vec<CollectionItem>> {
+        all_pages("items", async |continuation_token| {
+            let mut params = json!({ "page_size": PAGE_SIZE });
+            if let Some(token) = continuation_token {
+                params["continuation_token"] = json!(token);
+            }
+            self.get_items_by_collection_id(collection_id, Some(params))
+                .await
+        })
+        .await
+    }
+
+    /// Add items, returning the collection with its new `state_token`
+    pub async fn add_to_collection(
+        &self,
+        collection_id: &str,
+        asins: &[&str],
+    ) -> Result<Collection> {
+        let json = self
+            .with_state_token(collection_id, async |current: Collection| {
+                let params = json! {{
+                    "state_token": current.state_token,
+                    "collection_id": collection_id,
+                    "asins": asins,
+                }};
+                self.write_collection(Method::POST, collection_id, "/items", params)
+                    .await
+            })
+            .await?;
+        let mut collection: Collection = serde_json::from_value(json)?;
+        collection.collection_id = collection_id.to_string();
+        Ok(collection)
+    }
+
+    /// Remove items one at a time.
+    ///
+    /// Each delete only touches its own item, so it can't undo a concurrent change and
+    /// doesn't need a `state_token`.
+    pub async fn remove_from_collection(&self, collection_id: &str, asins: &[&str]) -> Result<()> {
+        for asin in asins {
+            let json = self
+                .delete_item_by_collection_id(collection_id, asin, None)
+                .await?;
+            check_message(json)?;
+        }
+        Ok(())
+    }
+
+    /// Put the items in the order of `asins`, asins missing from it keep their relative order at the end.
+    ///
+    /// The order is written by replacing the item list with [`Client::put_items_by_collection_id`].
+    /// The items are read again on every attempt, and the write carries the `state_token` of that
+    /// read, so an item added in between makes the write fail as stale instead of being dropped.
+    pub async fn reorder_collection(&self, collection_id: &str, asins: &[&str]) -> Result<()> {
+        self.with_state_token(collection_id, async |current: Collection| {
+            let items = self.get_collection_items(collection_id).await?;
+            let order = reorder(&items, asins);
+            let params = json! {{
+                "state_token": current.state_token,
+                "collection_id": collection_id,
+                "asins": order,
+            }};
+            self.write_collection(Method::PUT, collection_id, "/items", params)
+                .await
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Run a write with the current state of the collection, fetching it again and
+    /// retrying when the `state_token` went stale in between
+    async fn with_state_token(
+        &self,
+        collection_id: &str,
+        write: impl AsyncFn(Collection) -> Result<(StatusCode, Value)>,
+    ) -> Result<Value> {
+        retry_stale(async || self.get_collection(collection_id).await, write).await
+    }
+
+    /// Send a write to `/1.0/collections/(collection_id)` followed by `path`, keeping the
+    /// status to tell a stale `state_token` apart from other errors
+    async fn write_collection(
+        &self,
+        method: Method,
+        collection_id: &str,
+        path: &str,
+        params: Value,
+    ) -> Result<(StatusCode, Value)> {
+        let url = format!(
+            "{}/1.0/collections/{}{}",
+            self.base_url, collection_id, path
+        );
+        let req = self.client.request(method, url).json(&params).build()?;
+        let res = self.send_request(req).await?;
+        let status = res.status();
+        let json = match status.is_success() {
+            true => json_or_null(res).await?,
+            // the body of a rejected write may not be JSON
+            false => json_or_null(res).await.unwrap_or(Value::Null),
+        };
+        Ok((status, json))
+    }
+
+    /// GET /1.0/collections
     ///
     /// Query Parameters:
-    /// - `response_groups` (string) – [category_metadata, products]
-    /// - `products_plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, US Minerva, Universal, AmazonEnglish,
-    ///   ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
-    /// - `products_in_plan_timestamp` (string)
-    /// - `products_num_results` (integer)
-    /// - `runtime_length_min` (integer)
-    /// - `content_level` (string)
-    /// - `content_type` (string)
-    /// - `categories_num_levels` (integer) – (greater than or equal to 1)
-    /// - `ids` (string) – \d+(,\d+)*
-    /// - `root` (string) – [InstitutionsHpMarketing, ChannelsConfigurator, AEReadster, ShortsPrime, ExploreBy, RodizioBuckets, EditorsPicks,
-    ///   ClientContent, RodizioGenres, AmazonEnglishProducts, ShortsSandbox, Genres, Curated, ShortsIntroOutroRemoval, Shorts,
-    ///   RodizioEpisodesAndSeries, ShortsCurated]
-    pub async fn get_catalog_categories(&self, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/catalog/categories", self.base_url);
+    /// - `page_size` (integer)
+    /// - `continuation_token` (string)
+    /// - `state_token` (string) – [ey…]
+    /// - `visibility_types` (string) – [Private, Discoverable]
+    pub async fn get_collections(&self, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/collections", self.base_url);
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -35,27 +338,43 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/catalog/categories/(category_id)
+    /// POST /1.0/collections
+    /// Create a new collection
     ///
-    /// Parameters:
-    /// - `category_id` (string)
+    /// Request JSON Object:
+    /// - `name` (string)
+    /// - `asins` (array of strings) – []
+    /// - `description` (string)
     ///
-    /// Query Parameters:
-    /// - `image_dpi` (integer)
-    /// - `image_sizes` (string)
-    /// - `image_variants` (string)
-    /// - `products_in_plan_timestamp` (string)
-    /// - `products_num_results` (integer)
-    /// - `products_plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, AmazonEnglish, ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
-    /// - `products_sort_by` (string) – [-ReleaseDate, ContentLevel, -Title, AmazonEnglish, AvgRating, BestSellers, -RuntimeLength, ReleaseDate, ProductSiteLaunchDate, -ContentLevel, Title, Relevance, RuntimeLength]
-    /// - `reviews_num_results` (integer)
-    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
-    pub async fn get_catalog_category_by_id(
+    /// Response JSON Object:
+    /// - `collection_id` (string)
+    /// - `creation_date` (string)
+    /// - `customer_id` (string)
+    /// - `marketplace` (string)
+    pub async fn post_collections(&self, params: Option<Value>) -> Result<Value> {
+        let url = format!("{}/1.0/collections", self.base_url);
+
+        let mut req = self.client.post(url);
+        if let Some(params) = params {
+            req = req.json(&params);
+        }
+        let req = req.build()?;
+
+        let res = self.send_request(req).await?;
+        let json: Value = res.json().await?;
+        Ok(json)
+    }
+
+    /// GET /1.0/collections/(collection_id)
+    ///
+    /// Parameters:
+    /// - `collection_id` (string)
+    pub async fn get_collection_by_id(
         &self,
-        category_id: &str,
+        collection_id: &str,
         params: Option<Value>,
     ) -> Result<Value> {
-        let url = format!("{}/1.0/catalog/categories/{}", self.base_url, category_id);
+        let url = format!("{}/1.0/collections/{}", self.base_url, collection_id);
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -68,36 +387,33 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/catalog/products
+    /// PUT /1.0/collections/(collection_id)
+    /// Modify a collection
     ///
-    /// Query Parameters:
-    /// - `author` (string)
-    /// - `browse_type` (string)
-    /// - `category_id` (integer) – \d+(,\d+)*
-    /// - `disjunctive_category_ids` (string)
-    /// - `image_dpi` (integer)
-    /// - `image_sizes` (string)
-    /// - `in_plan_timestamp` (string)
-    /// - `keywords` (string)
-    /// - `narrator` (string)
-    /// - `not_in_plan_timestamp` (string)
-    /// - `num_most_recent` (integer)
-    /// - `num_results` (integer) – (max: 50)
-    /// - `page` (integer)
-    /// - `plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, AmazonEnglish, ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
-    /// - `products_since_timestamp` (string)
-    /// - `products_sort_by` (string) – [-ReleaseDate, ContentLevel, -Title, AmazonEnglish, AvgRating, BestSellers, -RuntimeLength, ReleaseDate, ProductSiteLaunchDate, -ContentLevel, Title, Relevance, RuntimeLength]
-    /// - `publisher` (string)
-    /// - `response_groups` (string) – [contributors, media, price, product_attrs, product_desc, product_extended_attrs, product_plan_details, product_plans, rating, review_attrs, reviews, sample, series, sku]
-    /// - `reviews_num_results` (integer) – (max: 10)
-    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
-    /// - `title` (string)
-    pub async fn get_products(&self, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/catalog/products", self.base_url);
+    /// Parameters:
+    /// - `collection_id` (string)
+    ///
+    /// Request JSON Object:
+    /// - `state_token` (string)
+    /// - `collection_id` (string)
+    /// - `name` (string)
+    /// - `description` (string)
+    ///
+    /// Response JSON Object:
+    /// - `state_token` (string)
+    /// - `collection_id` (string)
+    /// - `name` (string)
+    /// - `description` (string)
+    pub async fn put_collection_by_id(
+        &self,
+        collection_id: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!("{}/1.0/collections/{}", self.base_url, collection_id);
 
-        let mut req = self.client.get(url);
+        let mut req = self.client.put(url);
         if let Some(params) = params {
-            req = req.query(&params);
+            req = req.json(&params);
         }
         let req = req.build()?;
 
@@ -106,22 +422,21 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/catalog/products/(string:asin)
+    /// GET /1.0/collections/(collection_id)/items
     ///
     /// Parameters:
-    /// - `asin` (string) – The ASIN of the book
+    /// - `collection_id` (string) – e.g. __FAVORITES
     ///
     /// Query Parameters:
-    /// - `image_dpi` (integer)
-    /// - `image_sizes` (string)
-    /// - `response_groups` (string) – [contributors, media, price, product_attrs, product_desc, product_details, product_extended_attrs,
-    ///   product_plan_details, product_plans, rating, sample, sku, series, reviews, relationships, review_attrs, category_ladders,
-    ///   claim_code_url, provided_review, rights, customer_rights]
-    /// - `reviews_num_results` (integer) – \d+ (max: 10)
-    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
-    /// - `asins` (string)
-    pub async fn get_products_by_asin(&self, asin: &str, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/catalog/products/{}", self.base_url, asin);
+    /// - `page_size` (integer)
+    /// - `continuation_token` (string)
+    /// - `response_groups` (string) – [always-returned]
+    pub async fn get_items_by_collection_id(
+        &self,
+        collection_id: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!("{}/1.0/collections/{}/items", self.base_url, collection_id);
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -134,21 +449,31 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/catalog/products/(string:asin)/reviews
+    /// POST /1.0/collections/(collection_id)/items
+    /// Add item(s) to a collection
     ///
     /// Parameters:
-    /// - `asin` (string) – The ASIN of the book
+    /// - `collection_id` (string)
     ///
-    /// Query Parameters:
-    /// - `sort_by` (string) – [MostHelpful, MostRecent]
-    /// - `num_results` (integer) – (max: 50)
-    /// - `page` (integer)
-    pub async fn get_product_reviews(&self, asin: &str, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/catalog/products/{}/reviews", self.base_url, asin);
+    /// Request JSON Object:
+    /// - `collection_id` (string)
+    /// - `asins` (array of strings) – []
+    ///
+    /// Response JSON Object:
+    /// - `description` (string)
+    /// - `name` (string)
+    /// - `num_items_added` (integer)
+    /// - `state_token` (string)
+    pub async fn post_items_by_collection_id(
+        &self,
+        collection_id: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!("{}/1.0/collections/{}/items", self.base_url, collection_id);
 
-        let mut req = self.client.get(url);
+        let mut req = self.client.post(url);
         if let Some(params) = params {
-            req = req.query(&params);
+            req = req.json(&params);
         }
         let req = req.build()?;
 
@@ -157,35 +482,171 @@ impl Client {
         Ok(json)
     }
 
-    /// GET /1.0/catalog/products/(string:asin)/sims
+    /// DELETE /1.0/collections/(collection_id)
+    /// Delete a collection
     ///
     /// Parameters:
-    /// - `asin` (string) – The ASIN of the book
+    /// - `collection_id` (string)
+    pub async fn delete_collection_by_id(
+        &self,
+        collection_id: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!("{}/1.0/collections/{}", self.base_url, collection_id);
+
+        let mut req = self.client.delete(url);
+        if let Some(params) = params {
+            req = req.query(&params);
+        }
+        let req = req.build()?;
+
+        let res = self.send_request(req).await?;
+        json_or_null(res).await
+    }
+
+    /// PUT /1.0/collections/(collection_id)/items
+    /// Replace the items of a collection, e.g. to reorder them
     ///
-    /// Query Parameters:
-    /// - `category_image_variants` (string)
-    /// - `image_dpi` (integer)
-    /// - `image_sizes` (string)
-    /// - `in_plan_timestamp` (string)
-    /// - `language` (string)
-    /// - `not_in_plan_timestamp` (string)
-    /// - `num_results` (integer) – (max: 50)
-    /// - `plan` (string) – [Enterprise, RodizioFreeBasic, AyceRomance, AllYouCanEat, AmazonEnglish, ComplimentaryOriginalMemberBenefit, Radio, SpecialBenefit, Rodizio]
-    /// - `response_groups` (string) – [contributors, media, price, product_attrs, product_desc, product_extended_attrs, product_plans, rating, review_attrs, reviews, sample, sku]
-    /// - `reviews_num_results` (integer) – (max: 10)
-    /// - `reviews_sort_by` (string) – [MostHelpful, MostRecent]
-    /// - `similarity_type` (string) – [InTheSameSeries, ByTheSameNarrator, RawSimilarities, ByTheSameAuthor, NextInSameSeries]
-    pub async fn get_similar_products(&self, asin: &str, params: Option<Value>) -> Result<Value> {
-        let url = format!("{}/1.0/catalog/products/{}/sims", self.base_url, asin);
+    /// Parameters:
+    /// - `collection_id` (string)
+    ///
+    /// Request JSON Object:
+    /// - `state_token` (string)
+    /// - `collection_id` (string)
+    /// - `asins` (array of strings) – []
+    pub async fn put_items_by_collection_id(
+        &self,
+        collection_id: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!("{}/1.0/collections/{}/items", self.base_url, collection_id);
 
-        let mut req = self.client.get(url);
+        let mut req = self.client.put(url);
+        if let Some(params) = params {
+            req = req.json(&params);
+        }
+        let req = req.build()?;
+
+        let res = self.send_request(req).await?;
+        json_or_null(res).await
+    }
+
+    /// DELETE /1.0/collections/(collection_id)/items/(asin)
+    /// Remove an item from a collection
+    ///
+    /// Parameters:
+    /// - `collection_id` (string)
+    /// - `asin` (string)
+    pub async fn delete_item_by_collection_id(
+        &self,
+        collection_id: &str,
+        asin: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        let url = format!(
+            "{}/1.0/collections/{}/items/{}",
+            self.base_url, collection_id, asin
+        );
+
+        let mut req = self.client.delete(url);
         if let Some(params) = params {
             req = req.query(&params);
         }
         let req = req.build()?;
 
         let res = self.send_request(req).await?;
-        let json: Value = res.json().await?;
-        Ok(json)
+        json_or_null(res).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection() {
+        let collection: Collection = serde_json::from_value(json!({
+            "collection_id": "abc",
+            "name": "To listen",
+            "state_token": "ey123",
+            "visibility_type": "Private"
+        }))
+        .unwrap();
+        assert_eq!(collection.visibility, Some(Visibility::Private));
+        assert_eq!(collection.state_token.as_deref(), Some("ey123"));
+
+        assert!(is_stale_state(StatusCode::CONFLICT));
+        assert!(is_stale_state(StatusCode::PRECONDITION_FAILED));
+        assert!(!is_stale_state(StatusCode::BAD_REQUEST));
+    }
+
+    #[tokio::test]
+    async fn test_retry_stale() {
+        // stale twice, then accepted with the third state token
+        let mut fetched = 0;
+        let json = retry_stale(
+            async || {
+                fetched += 1;
+                Ok(format!("ey{}", fetched))
+            },
+            async |token: String| match token.as_str() {
+                "ey3" => Ok((StatusCode::OK, json!({"state_token": "ey4"}))),
+                _ => Ok((StatusCode::CONFLICT, Value::Null)),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(json["state_token"], "ey4");
+        assert_eq!(fetched, 3);
+
+        // gives up after the last attempt, and doesn't retry other errors
+        let stale = retry_stale(
+            async || Ok(()),
+            async |_| Ok((StatusCode::PRECONDITION_FAILED, Value::Null)),
+        )
+        .await;
+        assert!(stale.is_err());
+        let mut fetched = 0;
+        let rejected = retry_stale(
+            async || {
+                fetched += 1;
+                Ok(())
+            },
+            async |_| Ok((StatusCode::BAD_REQUEST, json!({"message": "Invalid asin"}))),
+        )
+        .await;
+        assert_eq!(rejected.unwrap_err().to_string(), "Invalid asin");
+        assert_eq!(fetched, 1);
+    }
+
+    #[test]
+    fn test_reorder() {
+        let items: Vec<CollectionItem> = ["A", "B", "C", "D"]
+            .into_iter()
+            .map(|asin| CollectionItem {
+                asin: asin.into(),
+                added_date: None,
+            })
+            .collect();
+        assert_eq!(reorder(&items, &["C", "X", "A"]), ["C", "A", "B", "D"]);
+    }
+
+    #[tokio::test]
+    async fn test_all_pages() {
+        let mut requested = Vec::new();
+        let items: Vec<CollectionItem> = all_pages("items", async |token| {
+            requested.push(token.map(String::from));
+            Ok(match token {
+                None => {
+                    json!({"items": [{"asin": "A"}, {"asin": "B"}], "continuation_token": "t1"})
+                }
+                Some("t1") => json!({"items": [{"asin": "C"}], "continuation_token": "t1"}),
+                Some(_) => unreachable!(),
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(requested, [None, Some("t1".to_string())]);
     }
 }
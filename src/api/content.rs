@@ -1,10 +1,115 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use std::collections::HashMap;
 
-use super::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::product::nullable;
+use super::response_groups::{join, ContentResponseGroup};
+use super::{take_field, Client};
 use crate::Result;
 
+/// The `chapter_info` response group of the content metadata
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChapterInfo {
+    #[serde(rename = "brandIntroDurationMs")]
+    pub brand_intro_duration_ms: Option<u64>,
+    #[serde(rename = "brandOutroDurationMs")]
+    pub brand_outro_duration_ms: Option<u64>,
+    pub is_accurate: Option<bool>,
+    pub runtime_length_ms: Option<u64>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub chapters: Vec<Chapter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Chapter {
+    pub title: String,
+    pub start_offset_ms: u64,
+    pub length_ms: u64,
+    /// Sub chapters, only present with `chapter_titles_type=Tree`
+    #[serde(default, deserialize_with = "nullable")]
+    pub chapters: Vec<Chapter>,
+}
+
+/// The `content_license` of a license request
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContentLicense {
+    pub asin: String,
+    /// Content reference, needed to update the last position heard
+    pub acr: Option<String>,
+    pub drm_type: Option<String>,
+    pub license_id: Option<String>,
+    /// `Granted` on success
+    pub status_code: Option<String>,
+    pub message: Option<String>,
+    pub license_response: Option<String>,
+    #[serde(default)]
+    pub content_metadata: ContentMetadata,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContentMetadata {
+    pub chapter_info: Option<ChapterInfo>,
+    /// e.g. `offline_url`, `streaming_url`
+    #[serde(default, deserialize_with = "nullable")]
+    pub content_url: HashMap<String, Value>,
+    pub content_reference: Option<Value>,
+}
+
+impl ContentLicense {
+    /// The license if its status is `Granted`, a missing status counts as not granted
+    fn granted(self) -> Result<Self> {
+        match self.status_code.as_deref() {
+            Some("Granted") => Ok(self),
+            status => Err(format!(
+                "License for {} not granted: {} {}",
+                self.asin,
+                status.unwrap_or("no status"),
+                self.message.as_deref().unwrap_or_default()
+            )
+            .into()),
+        }
+    }
+}
+
+impl ContentMetadata {
+    /// The url of the content, preferring `key` when present
+    pub fn url(&self, key: &str) -> Option<&str> {
+        self.content_url
+            .get(key)
+            .and_then(Value::as_str)
+            .or_else(|| self.content_url.values().find_map(Value::as_str))
+    }
+}
+
+impl ChapterInfo {
+    /// All chapters in playback order, with any sub chapters flattened in
+    pub fn flatten(&self) -> Vec<&Chapter> {
+        fn walk<'a>(chapters: &'a [Chapter], out: &mut Vec<&'a Chapter>) {
+            for chapter in chapters {
+                out.push(chapter);
+                walk(&chapter.chapters, out);
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.chapters, &mut out);
+        out
+    }
+}
+
 impl Client {
+    /// Typed chapter info of the book, from [`Client::get_content_metadata`]
+    pub async fn get_chapter_info(&self, asin: &str) -> Result<ChapterInfo> {
+        let params = json! {{
+            "response_groups": join(&[ContentResponseGroup::ChapterInfo]),
+            "chapter_titles_type": "Flat",
+        }};
+        let json = self.get_content_metadata(asin, Some(params)).await?;
+        let metadata: Value = take_field(json, "content_metadata")?;
+        take_field(metadata, "chapter_info")
+    }
+
     /// GET /1.0/content/(string:asin)/metadata
     ///
     /// Parameters:
@@ -30,6 +135,18 @@ impl Client {
         Ok(json)
     }
 
+    /// Typed version of [`Client::post_license_request`], erroring unless the license was granted.
+    ///
+    /// Unlike [`Client::post_license_request`], the params are sent as the JSON body
+    /// documented for the endpoint.
+    pub async fn get_content_license(&self, asin: &str, params: Value) -> Result<ContentLicense> {
+        let url = format!("{}/1.0/content/{}/licenserequest", self.base_url, asin);
+        let req = self.client.post(url).json(&params).build()?;
+        let json: Value = self.send_request(req).await?.json().await?;
+        let license: ContentLicense = take_field(json, "content_license")?;
+        license.granted()
+    }
+
     /// POST /1.0/content/(string:asin)/drmlicense
     ///
     /// Parameters:
@@ -135,3 +252,24 @@ impl Client {
         Ok(json)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_license_granted() {
+        let license = |json: Value| serde_json::from_value::<ContentLicense>(json).unwrap();
+        assert!(
+            license(json!({"asin": "B00TEST123", "status_code": "Granted"}))
+                .granted()
+                .is_ok()
+        );
+        assert!(
+            license(json!({"asin": "B00TEST123", "status_code": "Denied"}))
+                .granted()
+                .is_err()
+        );
+        assert!(license(json!({"asin": "B00TEST123"})).granted().is_err());
+    }
+}
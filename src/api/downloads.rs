@@ -0,0 +1,185 @@
+use std::path::{Path, PathBuf};
+
+use reqwest::header::ACCEPT;
+use reqwest::Response;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use url::Url;
+
+use super::response_groups::{join, LibraryResponseGroup};
+use super::Client;
+use crate::naming::{Fields, NamingTemplate, NAMING_RESPONSE_GROUPS};
+use crate::Result;
+
+/// All image sizes the library endpoint will return in `product_images`
+pub const IMAGE_SIZES: [u32; 10] = [1215, 408, 360, 882, 315, 570, 252, 558, 900, 500];
+
+/// Default layout for covers, `{image_size}` is available on top of the library item fields.
+///
+/// Templates for downloads must include `{asin}`, so that two items never share a path
+/// and an existing file is only skipped when it belongs to the same item.
+pub const COVER_TEMPLATE: &str = "{asin}/cover_{image_size}";
+pub const PDF_TEMPLATE: &str = "{asin}/{asin}";
+
+/// Result of a single file download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Download {
+    /// The file was written to the path
+    Downloaded(PathBuf),
+    /// A file already existed at the path, nothing was downloaded
+    Skipped(PathBuf),
+    /// The item has no such file, e.g. a book without a companion PDF
+    Unavailable,
+}
+
+impl Client {
+    /// Download the cover of a library item into `dir`, named by `naming` or [`COVER_TEMPLATE`].
+    ///
+    /// With `size` of `None` the largest available image is used.
+    pub async fn download_cover(
+        &self,
+        asin: &str,
+        size: Option<u32>,
+        dir: impl AsRef<Path>,
+        naming: Option<&NamingTemplate>,
+    ) -> Result<Download> {
+        let sizes = match size {
+            Some(size) => size.to_string(),
+            None => IMAGE_SIZES.map(|s| s.to_string()).join(","),
+        };
+        let params = json! {{
+            "response_groups": join(&[&[LibraryResponseGroup::Media], NAMING_RESPONSE_GROUPS].concat()),
+            "image_sizes": sizes,
+        }};
+        let item = self.get_library_item(asin, Some(params)).await?;
+
+        let image = match size {
+            Some(size) => item
+                .product
+                .product_images
+                .get(&size.to_string())
+                .map(|url| (size, url.as_str())),
+            None => item.product.largest_image_url().map(|url| {
+                let size = item
+                    .product
+                    .product_images
+                    .iter()
+                    .find(|(_, u)| u.as_str() == url)
+                    .and_then(|(size, _)| size.parse().ok())
+                    .unwrap_or_default();
+                (size, url)
+            }),
+        };
+        let Some((size, url)) = image else {
+            return Ok(Download::Unavailable);
+        };
+
+        let extension = cover_extension(url);
+        let default_naming;
+        let naming = match naming {
+            Some(naming) => check_naming(naming)?,
+            None => {
+                default_naming = NamingTemplate::new(COVER_TEMPLATE, &["image_size"])?;
+                &default_naming
+            }
+        };
+        let fields = Fields::new(&item).with("image_size", size);
+        let path = dir.as_ref().join(naming.render_fields(&fields, &extension));
+        if path.exists() {
+            return Ok(Download::Skipped(path));
+        }
+
+        // images are served from a public CDN and do not need to be signed
+        let res = self.client.get(url).header(ACCEPT, "*/*").send().await?;
+        save_response(res, &path).await?;
+        Ok(Download::Downloaded(path))
+    }
+
+    /// Download the companion PDF of a library item into `dir`, named by `naming` or [`PDF_TEMPLATE`]
+    pub async fn download_pdf(
+        &self,
+        asin: &str,
+        dir: impl AsRef<Path>,
+        naming: Option<&NamingTemplate>,
+    ) -> Result<Download> {
+        let params = json! {{
+            "response_groups": join(&[&[LibraryResponseGroup::PdfUrl], NAMING_RESPONSE_GROUPS].concat()),
+        }};
+        let item = self.get_library_item(asin, Some(params)).await?;
+        let Some(url) = item.pdf_url.clone() else {
+            return Ok(Download::Unavailable);
+        };
+
+        let path = match naming {
+            Some(naming) => check_naming(naming)?.render(&item, "pdf"),
+            None => NamingTemplate::new(PDF_TEMPLATE, &[])?.render(&item, "pdf"),
+        };
+        let path = dir.as_ref().join(path);
+        if path.exists() {
+            return Ok(Download::Skipped(path));
+        }
+
+        let req = self.client.get(url).header(ACCEPT, "*/*").build()?;
+        let res = self.send_request(req).await?;
+        save_response(res, &path).await?;
+        Ok(Download::Downloaded(path))
+    }
+}
+
+/// The extension of the file the url points to, ignoring any query or fragment
+fn cover_extension(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            let path = Path::new(url.path());
+            Some(path.extension()?.to_str()?.to_lowercase())
+        })
+        .unwrap_or_else(|| "jpg".to_string())
+}
+
+/// Download templates without `{asin}` could render two items to the same path
+fn check_naming(naming: &NamingTemplate) -> Result<&NamingTemplate> {
+    match naming.uses_field("asin") {
+        true => Ok(naming),
+        false => Err(format!(
+            "Download template must include {{asin}}: {}",
+            naming.template()
+        )
+        .into()),
+    }
+}
+
+/// Stream the response body to `path`, only moving it into place once complete
+pub(crate) async fn save_response(mut res: Response, path: &Path) -> Result<()> {
+    if !res.status().is_success() {
+        return Err(format!("Failed to download {}: {}", res.url(), res.status()).into());
+    }
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let tmp_path = path.with_extension("part");
+    let mut file = tokio::fs::File::create(&tmp_path).await?;
+    while let Some(chunk) = res.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_extension() {
+        assert_eq!(
+            cover_extension("https://m.media-amazon.com/images/I/51abc._SL500_.jpg?x=1.png"),
+            "jpg"
+        );
+        assert_eq!(cover_extension("https://example.com/cover.PNG#top"), "png");
+        assert_eq!(cover_extension("https://example.com/cover"), "jpg");
+        assert_eq!(cover_extension("not a url.png"), "jpg");
+    }
+}
@@ -1,7 +1,7 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
 use serde_json::Value;
 
-use super::Client;
+use super::{json_or_null, Client};
 use crate::Result;
 
 impl Client {
@@ -24,7 +24,6 @@ impl Client {
         let req = req.build()?;
 
         let res = self.send_request(req).await?;
-        let json: Value = res.json().await?;
-        Ok(json)
+        json_or_null(res).await
     }
 }
@@ -1,10 +1,63 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
 use json_value_merge::Merge;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
-use super::Client;
+use super::product::Product;
+use super::response_groups::{join, LibraryResponseGroup};
+use super::{take_field, Client};
 use crate::Result;
 
+/// Most items `get_library` returns per page
+pub const MAX_LIBRARY_PAGE_SIZE: u64 = 1000;
+
+/// A product in the user's library, along with its library specific fields
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LibraryItem {
+    #[serde(flatten)]
+    pub product: Product,
+    pub purchase_date: Option<String>,
+    pub status: Option<String>,
+    pub is_finished: Option<bool>,
+    pub percent_complete: Option<f64>,
+    pub pdf_url: Option<String>,
+    pub listening_status: Option<ListeningStatus>,
+    pub is_archived: Option<bool>,
+    pub is_downloaded: Option<bool>,
+    pub is_playable: Option<bool>,
+    pub is_removable: Option<bool>,
+    pub is_returnable: Option<bool>,
+    pub is_visible: Option<bool>,
+    pub in_wishlist: Option<bool>,
+    /// The `origin_asin` response group, the asin the item was bought as, e.g. a bundle
+    pub origin_asin: Option<String>,
+}
+
+/// The `listening_status` response group
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ListeningStatus {
+    pub is_finished: Option<bool>,
+    pub percent_complete: Option<f64>,
+    pub time_remaining_seconds: Option<u64>,
+    pub finished_at_timestamp: Option<String>,
+}
+
+impl LibraryItem {
+    /// Finished according to either the `is_finished` or `listening_status` response group
+    pub fn finished(&self) -> bool {
+        self.is_finished
+            .or(self.listening_status.as_ref().and_then(|s| s.is_finished))
+            .unwrap_or_default()
+    }
+
+    pub fn progress(&self) -> Option<f64> {
+        self.percent_complete.or(self
+            .listening_status
+            .as_ref()
+            .and_then(|s| s.percent_complete))
+    }
+}
+
 impl Client {
     /// GET /1.0/library
     ///
@@ -33,7 +86,10 @@ impl Client {
 
         let mut query = json! {{
                 "num_results": 1000,
-                "response_groups": "product_desc,product_attrs",
+                "response_groups": join(&[
+                    LibraryResponseGroup::ProductDesc,
+                    LibraryResponseGroup::ProductAttrs,
+                ]),
                 "sort_by": "-PurchaseDate"
         }};
         if let Some(params) = params {
@@ -46,6 +102,25 @@ impl Client {
         Ok(json)
     }
 
+    /// Typed version of [`Client::get_library`], fetching every page of results.
+    ///
+    /// `num_results` is clamped to 1 to 1000 items per page.
+    pub async fn get_library_items(&self, params: Option<Value>) -> Result<Vec<LibraryItem>> {
+        let mut params = params.unwrap_or_else(|| json!({}));
+        let num_results = params["num_results"]
+            .as_u64()
+            .unwrap_or(MAX_LIBRARY_PAGE_SIZE)
+            .clamp(1, MAX_LIBRARY_PAGE_SIZE);
+        params["num_results"] = json!(num_results);
+
+        all_pages(num_results, async |page| {
+            params["page"] = json!(page);
+            let json = self.get_library(Some(params.clone())).await?;
+            take_field(json, "items")
+        })
+        .await
+    }
+
     /// GET /1.0/library/(string:asin)
     ///
     /// Parameters:
@@ -74,6 +149,12 @@ impl Client {
         Ok(json)
     }
 
+    /// Typed version of [`Client::get_library_item_by_asin`]
+    pub async fn get_library_item(&self, asin: &str, params: Option<Value>) -> Result<LibraryItem> {
+        let json = self.get_library_item_by_asin(asin, params).await?;
+        take_field(json, "item")
+    }
+
     /// POST /1.0/library/item
     ///
     /// Request JSON Object:
@@ -309,11 +390,91 @@ impl Client {
     }
 }
 
+/// Every page from the first, until one is empty or short of `num_results` items
+async fn all_pages(
+    num_results: u64,
+    mut fetch: impl AsyncFnMut(u64) -> Result<Vec<LibraryItem>>,
+) -> Result<Vec<LibraryItem>> {
+    let mut items = Vec::new();
+    for page in 1.. {
+        let page_items = fetch(page).await?;
+        let len = page_items.len() as u64;
+        items.extend(page_items);
+        if len == 0 || len < num_results {
+            break;
+        }
+    }
+    Ok(items)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::auth::Auth;
 
+    #[test]
+    fn test_library_item() {
+        let json = json!({
+            "asin": "B00EPISODE",
+            "title": "Episode 12",
+            "is_finished": null,
+            "listening_status": {"is_finished": true, "percent_complete": 100.0},
+            "relationships": [
+                {"asin": "B00PARENT", "relationship_to_product": "parent",
+                 "relationship_type": "episode", "sequence": 12},
+                {"asin": "B00SEASON", "relationship_to_product": "parent",
+                 "relationship_type": "season", "sequence": "2"}
+            ]
+        });
+        let item: LibraryItem = serde_json::from_value(json).unwrap();
+        assert!(item.finished());
+        assert_eq!(item.progress(), Some(100.0));
+        let sequences: Vec<_> = item
+            .product
+            .relationships
+            .iter()
+            .map(|r| r.sequence.as_deref())
+            .collect();
+        assert_eq!(sequences, [Some("12"), Some("2")]);
+    }
+
+    #[tokio::test]
+    async fn test_all_pages() {
+        let item = |asin: &str| LibraryItem {
+            product: Product {
+                asin: asin.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut requested = Vec::new();
+        let items = all_pages(2, async |page| {
+            requested.push(page);
+            Ok(match page {
+                1 => vec![item("A"), item("B")],
+                2 => vec![item("C")],
+                _ => unreachable!(),
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(requested, [1, 2]);
+
+        // a full page followed by an empty one
+        let mut requested = 0;
+        let items = all_pages(1, async |page| {
+            requested += 1;
+            Ok(match page {
+                1 => vec![item("A")],
+                _ => vec![],
+            })
+        })
+        .await
+        .unwrap();
+        assert_eq!((items.len(), requested), (1, 2));
+    }
+
     #[tokio::test]
     async fn test_get_library() {
         let auth = Auth::default("us").await.unwrap();
@@ -1,4 +1,8 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
 use reqwest::{Request, Response};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use url::Url;
 
 use crate::auth::auth_headers::auth_headers;
 use crate::auth::Auth;
@@ -12,19 +16,26 @@ pub mod catalog;
 pub mod collections;
 pub mod content;
 pub mod customer;
+pub mod downloads;
 pub mod last_positions;
 pub mod library;
 pub mod sidecar;
 pub mod orders;
 pub mod pages;
+pub mod podcasts;
+pub mod product;
 pub mod recommendations;
+pub mod response_groups;
+pub mod reviews;
 pub mod stats;
 pub mod user;
 pub mod wishlist;
 
 const API_URL: &str = "https://api.audible.";
+/// User agent of every request, e.g. `audible_api/0.1.0`
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Client {
     client: reqwest::Client,
     auth: Auth,
@@ -49,6 +60,7 @@ impl Client {
 
         let client = reqwest::Client::builder()
             .default_headers(headers)
+            .user_agent(USER_AGENT)
             .cookie_store(true)
             .build()?;
 
@@ -64,7 +76,7 @@ impl Client {
             Some(body) => body.as_bytes().unwrap_or_default().to_vec(),
             None => Vec::new(),
         };
-        let path = request.url().to_string().replace(&self.base_url, "");
+        let path = signing_path(request.url());
         let auth_headers = auth_headers(
             request.method().as_str(),
             &path,
@@ -77,3 +89,73 @@ impl Client {
         Ok(self.client.execute(request).await?)
     }
 }
+
+/// Deserialize `field` out of a response body, surfacing the API's error message if it is missing
+pub(crate) fn take_field<T: DeserializeOwned>(mut json: Value, field: &str) -> Result<T> {
+    match json.get_mut(field) {
+        Some(value) => Ok(serde_json::from_value(value.take())?),
+        None => match json["message"].as_str() {
+            Some(message) => Err(message.into()),
+            None => Err(format!("Missing {} in response", field).into()),
+        },
+    }
+}
+
+/// Parse a response body as JSON, treating an empty body (e.g. `204 No Content`) as `null`
+pub(crate) async fn json_or_null(res: Response) -> Result<Value> {
+    let status = res.status();
+    let bytes = res.bytes().await?;
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return match status.is_success() {
+            true => Ok(Value::Null),
+            false => Err(format!("Request failed: {}", status).into()),
+        };
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// The path and query that is signed, relative to whichever host the request goes to
+/// (e.g. the CDE host of the sidecar), not just `base_url`
+fn signing_path(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+/// Timestamps come as either RFC 3339 or e.g. `2021-08-17 18:24:01.442` in UTC
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .map(|t| t.and_utc())
+        })
+}
+
+/// RFC 3339 with milliseconds, e.g. `2020-10-23T21:29:06.985Z`, as the stats endpoints expect
+pub(crate) fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_path() {
+        let url = Url::parse("https://api.audible.com/1.0/library?num_results=10").unwrap();
+        assert_eq!(signing_path(&url), "/1.0/library?num_results=10");
+
+        let url = Url::parse(
+            "https://cde-ta-g7g.amazon.com/FionaCDEServiceEngine/sidecar?type=AUDI&key=B00TEST123",
+        )
+        .unwrap();
+        assert_eq!(
+            signing_path(&url),
+            "/FionaCDEServiceEngine/sidecar?type=AUDI&key=B00TEST123"
+        );
+    }
+}
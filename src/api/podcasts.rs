@@ -0,0 +1,139 @@
+use chrono::NaiveDate;
+use serde_json::json;
+
+use super::library::LibraryItem;
+use super::response_groups::{join, LibraryResponseGroup};
+use super::Client;
+use crate::Result;
+
+/// Response groups needed for podcast parents and episodes
+const PODCAST_RESPONSE_GROUPS: &[LibraryResponseGroup] = &[
+    LibraryResponseGroup::ProductAttrs,
+    LibraryResponseGroup::ProductDesc,
+    LibraryResponseGroup::Contributors,
+    LibraryResponseGroup::Relationships,
+    LibraryResponseGroup::ListeningStatus,
+    LibraryResponseGroup::IsFinished,
+    LibraryResponseGroup::PercentComplete,
+    LibraryResponseGroup::Periodicals,
+];
+
+/// Content delivery types of the parent of a podcast or periodical
+const PARENT_DELIVERY_TYPES: [&str; 2] = ["PodcastParent", "Periodical"];
+
+/// A podcast episode, or an issue of a periodical, in the library
+#[derive(Debug, Clone)]
+pub struct PodcastEpisode {
+    pub parent_asin: String,
+    pub release_date: Option<NaiveDate>,
+    pub is_finished: bool,
+    pub item: LibraryItem,
+}
+
+impl PodcastEpisode {
+    fn new(parent_asin: &str, item: LibraryItem) -> Self {
+        let release_date = item
+            .product
+            .release_date
+            .as_deref()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+        Self {
+            parent_asin: parent_asin.to_string(),
+            release_date,
+            is_finished: item.finished(),
+            item,
+        }
+    }
+}
+
+/// A podcast or periodical rather than one of its episodes
+fn is_parent(item: &LibraryItem) -> bool {
+    let delivery_type = item.product.content_delivery_type.as_deref();
+    delivery_type.is_some_and(|t| PARENT_DELIVERY_TYPES.contains(&t))
+        || (item.product.content_type.as_deref() == Some("Podcast")
+            && item.product.has_children == Some(true))
+}
+
+impl Client {
+    /// The podcasts and periodicals in the library, i.e. the parents of episodes
+    pub async fn get_podcasts(&self) -> Result<Vec<LibraryItem>> {
+        let params = json! {{
+            "response_groups": join(PODCAST_RESPONSE_GROUPS),
+            "sort_by": "Title",
+        }};
+        let items = self.get_library_items(Some(params)).await?;
+        Ok(items.into_iter().filter(is_parent).collect())
+    }
+
+    /// Every episode of a podcast, newest first
+    pub async fn get_podcast_episodes(&self, parent_asin: &str) -> Result<Vec<PodcastEpisode>> {
+        let params = json! {{
+            "parent_asin": parent_asin,
+            "response_groups": join(PODCAST_RESPONSE_GROUPS),
+        }};
+        let items = self.get_library_items(Some(params)).await?;
+        let mut episodes: Vec<_> = items
+            .into_iter()
+            .map(|item| PodcastEpisode::new(parent_asin, item))
+            .collect();
+        episodes.sort_by_key(|e| std::cmp::Reverse(e.release_date));
+        Ok(episodes)
+    }
+
+    /// Episodes of every podcast in the library released after `since`, newest first
+    pub async fn get_new_podcast_episodes(&self, since: NaiveDate) -> Result<Vec<PodcastEpisode>> {
+        let mut new_episodes = Vec::new();
+        for podcast in self.get_podcasts().await? {
+            let episodes = self.get_podcast_episodes(&podcast.product.asin).await?;
+            new_episodes.extend(
+                episodes
+                    .into_iter()
+                    .filter(|episode| episode.release_date.is_some_and(|date| date > since)),
+            );
+        }
+        new_episodes.sort_by_key(|e| std::cmp::Reverse(e.release_date));
+        Ok(new_episodes)
+    }
+
+    /// Unfinished episodes of a podcast, oldest first
+    pub async fn get_unfinished_podcast_episodes(
+        &self,
+        parent_asin: &str,
+    ) -> Result<Vec<PodcastEpisode>> {
+        let mut episodes: Vec<_> = self
+            .get_podcast_episodes(parent_asin)
+            .await?
+            .into_iter()
+            .filter(|episode| !episode.is_finished)
+            .collect();
+        episodes.reverse();
+        Ok(episodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_podcast_episode() {
+        let parent: LibraryItem = serde_json::from_value(json!({
+            "asin": "B00PARENT",
+            "content_delivery_type": "PodcastParent",
+        }))
+        .unwrap();
+        assert!(is_parent(&parent));
+
+        let item: LibraryItem = serde_json::from_value(json!({
+            "asin": "B00EPISODE",
+            "content_delivery_type": "PodcastEpisode",
+            "release_date": "2024-03-01",
+            "is_finished": true,
+        }))
+        .unwrap();
+        assert!(!is_parent(&item));
+        let episode = PodcastEpisode::new("B00PARENT", item);
+        assert_eq!(episode.release_date, NaiveDate::from_ymd_opt(2024, 3, 1));
+        assert!(episode.is_finished);
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::reviews::Review;
+
+/// A product as returned by the catalog, library and wishlist endpoints.
+///
+/// Which fields are populated depends on the `response_groups` requested;
+/// everything outside of `asin` is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Product {
+    pub asin: String,
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub authors: Vec<Contributor>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub narrators: Vec<Contributor>,
+    pub publisher_name: Option<String>,
+    pub publisher_summary: Option<String>,
+    pub merchandising_summary: Option<String>,
+    pub extended_product_description: Option<String>,
+    pub release_date: Option<String>,
+    pub issue_date: Option<String>,
+    pub language: Option<String>,
+    pub format_type: Option<String>,
+    pub content_type: Option<String>,
+    pub content_delivery_type: Option<String>,
+    pub runtime_length_min: Option<u64>,
+    pub has_children: Option<bool>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub episode_number: Option<String>,
+    /// e.g. `full`, `trailer`, `bonus`
+    pub episode_type: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub relationships: Vec<Relationship>,
+    pub copyright: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub series: Vec<Series>,
+    /// Image urls keyed by size, e.g. `"500"`
+    #[serde(default, deserialize_with = "nullable")]
+    pub product_images: HashMap<String, String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub category_ladders: Vec<CategoryLadder>,
+    pub price: Option<Price>,
+    /// The `product_plans` response group
+    #[serde(default, deserialize_with = "nullable")]
+    pub plans: Vec<Plan>,
+    /// The `rating` response group
+    pub rating: Option<Rating>,
+    /// The `reviews` response group
+    #[serde(default, deserialize_with = "nullable")]
+    pub customer_reviews: Vec<Review>,
+    /// The `provided_review` response group, the user's own review
+    pub provided_review: Option<Review>,
+    /// The `customer_rights` response group
+    pub customer_rights: Option<CustomerRights>,
+    /// The `sku` response group
+    pub sku: Option<String>,
+    pub sku_lite: Option<String>,
+    /// The `sample` response group
+    pub sample_url: Option<String>,
+    pub is_adult_product: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Contributor {
+    pub asin: Option<String>,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Series {
+    pub asin: Option<String>,
+    pub title: String,
+    /// Position within the series, e.g. `"1"` or `"2.5"`
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub sequence: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Link to a parent or child product, e.g. a podcast and its episodes or a series and its volumes
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Relationship {
+    pub asin: String,
+    pub title: Option<String>,
+    /// `parent` or `child`
+    pub relationship_to_product: Option<String>,
+    /// e.g. `episode`, `season`, `series`, `component`
+    pub relationship_type: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub sequence: Option<String>,
+    pub sort: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CategoryLadder {
+    pub root: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub ladder: Vec<CategoryRef>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CategoryRef {
+    pub id: String,
+    pub name: String,
+}
+
+/// The `price` response group
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Price {
+    pub list_price: Option<Amount>,
+    /// The lowest price currently available, e.g. a sale or member price
+    pub lowest_price: Option<Amount>,
+    pub credit_price: Option<f64>,
+    pub is_free_eligible: Option<bool>,
+    pub is_credit_price_eligible: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Amount {
+    pub base: f64,
+    pub currency_code: Option<String>,
+    /// e.g. `list`, `sale`, `member`
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+/// A plan the product is available in, e.g. the Plus catalog
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub plan_name: String,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+}
+
+/// The `rating` response group
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Rating {
+    pub num_reviews: Option<u64>,
+    pub overall_distribution: Option<RatingDistribution>,
+    pub performance_distribution: Option<RatingDistribution>,
+    pub story_distribution: Option<RatingDistribution>,
+}
+
+/// Number of ratings of each star count, along with their average
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RatingDistribution {
+    pub average_rating: Option<f64>,
+    /// e.g. `"4.5"`
+    pub display_average_rating: Option<String>,
+    /// The average rounded to half stars
+    pub display_stars: Option<f64>,
+    pub num_ratings: Option<u64>,
+    pub num_five_star_ratings: Option<u64>,
+    pub num_four_star_ratings: Option<u64>,
+    pub num_three_star_ratings: Option<u64>,
+    pub num_two_star_ratings: Option<u64>,
+    pub num_one_star_ratings: Option<u64>,
+}
+
+/// The `customer_rights` response group
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CustomerRights {
+    pub is_consumable: Option<bool>,
+    pub is_consumable_indefinitely: Option<bool>,
+    pub is_consumable_offline: Option<bool>,
+    pub is_consumable_until: Option<String>,
+}
+
+impl Price {
+    /// The price to pay right now, the lowest price when there is one
+    pub fn current(&self) -> Option<&Amount> {
+        self.lowest_price.as_ref().or(self.list_price.as_ref())
+    }
+}
+
+impl Plan {
+    /// Plans of the catalog included with membership, e.g. `US Minerva`
+    pub fn is_plus_catalog(&self) -> bool {
+        self.plan_name.contains("Minerva") || self.plan_name == "AYCL"
+    }
+}
+
+impl Product {
+    pub fn author_names(&self) -> Vec<&str> {
+        self.authors.iter().map(|a| a.name.as_str()).collect()
+    }
+
+    pub fn narrator_names(&self) -> Vec<&str> {
+        self.narrators.iter().map(|n| n.name.as_str()).collect()
+    }
+
+    /// The url of the largest image in `product_images`
+    pub fn largest_image_url(&self) -> Option<&str> {
+        self.product_images
+            .iter()
+            .filter_map(|(size, url)| size.parse::<u32>().ok().map(|size| (size, url)))
+            .max_by_key(|(size, _)| *size)
+            .map(|(_, url)| url.as_str())
+    }
+
+    /// Included with membership, requires the `product_plans` response group
+    pub fn in_plus_catalog(&self) -> bool {
+        self.plans.iter().any(Plan::is_plus_catalog)
+    }
+
+    /// The first category under the `Genres` root, e.g. `"Science Fiction & Fantasy"`
+    pub fn genre(&self) -> Option<&str> {
+        self.category_ladders
+            .iter()
+            .find(|ladder| ladder.root.as_deref() == Some("Genres"))
+            .or(self.category_ladders.first())
+            .and_then(|ladder| ladder.ladder.first())
+            .map(|category| category.name.as_str())
+    }
+}
+
+/// Treat an explicit `null` the same as a missing field
+pub(crate) fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Some fields are sent as a string by one endpoint and as a number by another
+pub(crate) fn string_or_number<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(s)) => Some(s),
+            Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+            _ => None,
+        },
+    )
+}
@@ -1,10 +1,32 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use serde_json::{json, Value};
 
-use super::Client;
+use super::product::Product;
+use super::response_groups::{join, RecommendationsResponseGroup};
+use super::{take_field, Client};
 use crate::Result;
 
+/// Most products `get_recommendations` returns
+pub const MAX_NUM_RESULTS: u32 = 50;
+
 impl Client {
+    /// Typed version of [`Client::get_recommendations`], `language` being e.g. `english`
+    pub async fn get_recommended_products(
+        &self,
+        response_groups: &[RecommendationsResponseGroup],
+        language: Option<&str>,
+    ) -> Result<Vec<Product>> {
+        let mut params = json! {{
+            "response_groups": join(response_groups),
+            "num_results": MAX_NUM_RESULTS,
+        }};
+        if let Some(language) = language {
+            params["language"] = json!(language);
+        }
+        let json = self.get_recommendations(Some(params)).await?;
+        take_field(json, "products")
+    }
+
     /// GET /1.0/recommendations
     ///
     /// Query Parameters:
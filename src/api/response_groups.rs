@@ -0,0 +1,205 @@
+//! The `response_groups` accepted by each endpoint.
+//!
+//! Every endpoint takes them as one comma separated string, built from the typed groups by
+//! [`join`], e.g. `join(&[LibraryResponseGroup::ProductAttrs, LibraryResponseGroup::Series])`.
+use serde::{Deserialize, Serialize};
+
+use super::catalog::query_value;
+
+/// The comma separated form of `groups`, as expected by the `response_groups` parameter
+pub fn join<T: Serialize>(groups: &[T]) -> String {
+    let groups: Vec<String> = groups.iter().map(query_value).collect();
+    groups.join(",")
+}
+
+/// Groups of [`Client::get_library`](super::Client::get_library) and
+/// [`Client::get_library_item_by_asin`](super::Client::get_library_item_by_asin)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryResponseGroup {
+    Contributors,
+    CustomerRights,
+    Media,
+    Price,
+    ProductAttrs,
+    ProductDesc,
+    ProductDetails,
+    ProductExtendedAttrs,
+    ProductPlanDetails,
+    ProductPlans,
+    Rating,
+    Sample,
+    Sku,
+    Series,
+    Reviews,
+    Ws4v,
+    Origin,
+    Relationships,
+    ReviewAttrs,
+    Categories,
+    BadgeTypes,
+    CategoryLadders,
+    ClaimCodeUrl,
+    InWishlist,
+    IsArchived,
+    IsDownloaded,
+    IsFinished,
+    IsPlayable,
+    IsRemovable,
+    IsReturnable,
+    IsVisible,
+    ListeningStatus,
+    OrderDetails,
+    OriginAsin,
+    PdfUrl,
+    PercentComplete,
+    Periodicals,
+    ProvidedReview,
+}
+
+/// Groups of the catalog product endpoints.
+///
+/// [`Client::get_products`](super::Client::get_products) and
+/// [`Client::get_similar_products`](super::Client::get_similar_products) accept the groups up to
+/// `Sku`, the others are only returned for a single product by
+/// [`Client::get_products_by_asin`](super::Client::get_products_by_asin).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CatalogResponseGroup {
+    Contributors,
+    Media,
+    Price,
+    ProductAttrs,
+    ProductDesc,
+    ProductExtendedAttrs,
+    ProductPlanDetails,
+    ProductPlans,
+    Rating,
+    ReviewAttrs,
+    Reviews,
+    Sample,
+    Series,
+    Sku,
+    ProductDetails,
+    Relationships,
+    CategoryLadders,
+    ClaimCodeUrl,
+    ProvidedReview,
+    Rights,
+    CustomerRights,
+}
+
+/// Groups of [`Client::get_wishlist`](super::Client::get_wishlist)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WishlistResponseGroup {
+    Contributors,
+    Media,
+    Price,
+    ProductAttrs,
+    ProductDesc,
+    ProductExtendedAttrs,
+    ProductPlanDetails,
+    ProductPlans,
+    Rating,
+    Sample,
+    Sku,
+    CustomerRights,
+    Relationships,
+}
+
+/// Groups of [`Client::get_recommendations`](super::Client::get_recommendations)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendationsResponseGroup {
+    Contributors,
+    Media,
+    Price,
+    ProductAttrs,
+    ProductDesc,
+    ProductExtendedAttrs,
+    ProductPlanDetails,
+    ProductPlans,
+    Rating,
+    Sample,
+    Sku,
+}
+
+/// Groups of [`Client::get_content_metadata`](super::Client::get_content_metadata) and
+/// [`Client::post_license_request`](super::Client::post_license_request).
+///
+/// `AlwaysReturned` and `ContentUrl` only apply to the metadata, the groups from
+/// `LastPositionHeard` on only to the license request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentResponseGroup {
+    ChapterInfo,
+    #[serde(rename = "always-returned")]
+    AlwaysReturned,
+    ContentReference,
+    ContentUrl,
+    LastPositionHeard,
+    PdfUrl,
+    AdInsertion,
+    Certificate,
+}
+
+/// Groups of [`Client::get_customer_information`](super::Client::get_customer_information)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomerResponseGroup {
+    MigrationDetails,
+    SubscriptionDetailsRodizio,
+    SubscriptionDetailsPremium,
+    CustomerSegment,
+    SubscriptionDetailsChannels,
+}
+
+/// Groups of [`Client::get_account_information`](super::Client::get_account_information)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountResponseGroup {
+    DelinquencyStatus,
+    CustomerBenefits,
+    CustomerSegments,
+    SubscriptionDetailsPaymentInstrument,
+    PlanSummary,
+    SubscriptionDetails,
+    DirectedIds,
+}
+
+/// Groups of [`Client::get_listening_stats`](super::Client::get_listening_stats)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsResponseGroup {
+    TotalListeningStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(&[
+                LibraryResponseGroup::Ws4v,
+                LibraryResponseGroup::IsFinished,
+                LibraryResponseGroup::ProvidedReview,
+            ]),
+            "ws4v,is_finished,provided_review"
+        );
+        assert_eq!(
+            join(&[
+                ContentResponseGroup::ChapterInfo,
+                ContentResponseGroup::AlwaysReturned,
+            ]),
+            "chapter_info,always-returned"
+        );
+        assert_eq!(
+            join(&[AccountResponseGroup::SubscriptionDetailsPaymentInstrument]),
+            "subscription_details_payment_instrument"
+        );
+        assert_eq!(join::<CatalogResponseGroup>(&[]), "");
+    }
+}
@@ -0,0 +1,259 @@
+//! Typed reviews of a product and the user's own reviews
+//!
+//! Reviews are read only: the external API docs list no endpoint to post or edit a review,
+//! so there is nothing known to be accepted to send one to.
+/// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::catalog::{query_value, ReviewsSortBy};
+use super::product::{nullable, RatingDistribution};
+use super::response_groups::{join, LibraryResponseGroup};
+use super::Client;
+use crate::Result;
+
+/// Most reviews `get_product_reviews` returns per page
+pub const MAX_REVIEWS_PER_PAGE: u64 = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Review {
+    pub id: Option<String>,
+    pub asin: Option<String>,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub author_id: Option<String>,
+    pub author_name: Option<String>,
+    pub location: Option<String>,
+    pub submission_date: Option<String>,
+    #[serde(default)]
+    pub ratings: Ratings,
+    pub review_content_scores: Option<ContentScores>,
+}
+
+/// Stars from 1 to 5, a review may leave out any of them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ratings {
+    pub overall_rating: Option<u8>,
+    pub performance_rating: Option<u8>,
+    pub story_rating: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContentScores {
+    pub content_quality: Option<f64>,
+    pub num_helpful_votes: Option<u64>,
+    pub num_unhelpful_votes: Option<u64>,
+}
+
+impl Review {
+    pub fn helpful_votes(&self) -> u64 {
+        self.review_content_scores
+            .and_then(|s| s.num_helpful_votes)
+            .unwrap_or_default()
+    }
+}
+
+/// Number of ratings of each star count, `counts[0]` being one star
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Distribution {
+    pub counts: [u64; 5],
+}
+
+impl Distribution {
+    /// Count the rating, ignoring anything outside 1 to 5 stars
+    pub fn add(&mut self, stars: Option<u8>) {
+        if let Some(stars @ 1..=5) = stars {
+            self.counts[stars as usize - 1] += 1;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn average(&self) -> Option<f64> {
+        let total = self.total();
+        let stars: u64 = (1..).zip(self.counts).map(|(stars, n)| stars * n).sum();
+        (total > 0).then(|| stars as f64 / total as f64)
+    }
+}
+
+impl From<&RatingDistribution> for Distribution {
+    fn from(distribution: &RatingDistribution) -> Self {
+        let counts = [
+            distribution.num_one_star_ratings,
+            distribution.num_two_star_ratings,
+            distribution.num_three_star_ratings,
+            distribution.num_four_star_ratings,
+            distribution.num_five_star_ratings,
+        ];
+        Self {
+            counts: counts.map(Option::unwrap_or_default),
+        }
+    }
+}
+
+/// Distributions of each kind of rating
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RatingSummary {
+    pub overall: Distribution,
+    pub performance: Distribution,
+    pub story: Distribution,
+}
+
+impl RatingSummary {
+    pub fn from_reviews(reviews: &[Review]) -> Self {
+        let mut summary = Self::default();
+        for review in reviews {
+            summary.overall.add(review.ratings.overall_rating);
+            summary.performance.add(review.ratings.performance_rating);
+            summary.story.add(review.ratings.story_rating);
+        }
+        summary
+    }
+}
+
+/// The reviews of a product one page at a time, see [`Client::reviews`]
+#[derive(Debug)]
+pub struct ReviewPages<'a> {
+    client: &'a Client,
+    asin: String,
+    sort_by: ReviewsSortBy,
+    page: u64,
+    done: bool,
+}
+
+impl ReviewPages<'_> {
+    /// The next page of reviews, `None` once every page was returned
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Review>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let params = json! {{
+            "sort_by": query_value(&self.sort_by),
+            "num_results": MAX_REVIEWS_PER_PAGE,
+            "page": self.page,
+        }};
+        let json = self
+            .client
+            .get_product_reviews(&self.asin, Some(params))
+            .await?;
+        let reviews = parse_reviews(json)?;
+        self.page += 1;
+        self.done = (reviews.len() as u64) < MAX_REVIEWS_PER_PAGE;
+        match reviews.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(reviews)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReviewsPage {
+    #[serde(default, deserialize_with = "nullable")]
+    customer_reviews: Vec<Review>,
+}
+
+/// Reviews are returned either at the top level or within the product
+fn parse_reviews(mut json: Value) -> Result<Vec<Review>> {
+    if let Some(message) = json["message"].as_str() {
+        return Err(message.into());
+    }
+    let page = match json.get_mut("product") {
+        Some(product) => product.take(),
+        None => json,
+    };
+    let page: ReviewsPage = serde_json::from_value(page)?;
+    Ok(page.customer_reviews)
+}
+
+impl Client {
+    /// Page through every review of the product
+    pub fn reviews(&self, asin: &str, sort_by: ReviewsSortBy) -> ReviewPages<'_> {
+        ReviewPages {
+            client: self,
+            asin: asin.to_string(),
+            sort_by,
+            page: 1,
+            done: false,
+        }
+    }
+
+    /// Every review of the product, stopping after `max_results`
+    pub async fn get_all_reviews(
+        &self,
+        asin: &str,
+        sort_by: ReviewsSortBy,
+        max_results: usize,
+    ) -> Result<Vec<Review>> {
+        let mut pages = self.reviews(asin, sort_by);
+        let mut reviews = Vec::new();
+        while reviews.len() < max_results {
+            match pages.next_page().await? {
+                Some(page) => reviews.extend(page),
+                None => break,
+            }
+        }
+        reviews.truncate(max_results);
+        Ok(reviews)
+    }
+
+    /// The reviews the user wrote of library items, from the `provided_review` response group
+    pub async fn get_provided_reviews(&self) -> Result<Vec<Review>> {
+        let params = json! {{
+            "response_groups": join(&[
+                LibraryResponseGroup::ProductAttrs,
+                LibraryResponseGroup::ProvidedReview,
+            ]),
+        }};
+        let library = self.get_library_items(Some(params)).await?;
+        Ok(library
+            .into_iter()
+            .filter_map(|item| {
+                let asin = item.product.asin;
+                item.product.provided_review.map(|mut review| {
+                    review.asin.get_or_insert(asin);
+                    review
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reviews() {
+        let json = json!({
+            "product": {
+                "asin": "B00TEST123",
+                "customer_reviews": [
+                    {
+                        "id": "R1",
+                        "title": "Great",
+                        "author_name": "Jane",
+                        "ratings": {"overall_rating": 5, "performance_rating": 4, "story_rating": 5},
+                        "review_content_scores": {"num_helpful_votes": 12, "num_unhelpful_votes": 1}
+                    },
+                    {
+                        "id": "R2",
+                        "ratings": {"overall_rating": 2, "performance_rating": null, "story_rating": 1}
+                    }
+                ]
+            }
+        });
+        let reviews = parse_reviews(json).unwrap();
+        assert_eq!(reviews.len(), 2);
+        assert_eq!(reviews[0].helpful_votes(), 12);
+        assert!(parse_reviews(json!({"message": "Not found"})).is_err());
+
+        let summary = RatingSummary::from_reviews(&reviews);
+        assert_eq!(summary.overall.counts, [0, 1, 0, 0, 1]);
+        assert_eq!(summary.overall.average(), Some(3.5));
+        assert_eq!(summary.performance.total(), 1);
+        assert_eq!(summary.story.average(), Some(3.0));
+        assert_eq!(Distribution::default().average(), None);
+    }
+}
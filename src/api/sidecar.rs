@@ -1,9 +1,143 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use super::Client;
+use super::{json_or_null, parse_timestamp, take_field, Client};
 use crate::Result;
 
+const SIDECAR_URL: &str = "https://cde-ta-g7g.amazon.com/FionaCDEServiceEngine/sidecar";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub id: String,
+    pub position_ms: u64,
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Note {
+    pub id: String,
+    pub start_ms: u64,
+    pub end_ms: Option<u64>,
+    pub text: String,
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub id: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// Note attached to the clip
+    pub text: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// The clips, notes and bookmarks of a book
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Annotations {
+    pub asin: String,
+    pub bookmarks: Vec<Bookmark>,
+    pub notes: Vec<Note>,
+    pub clips: Vec<Clip>,
+    pub last_heard_ms: Option<u64>,
+}
+
+/// A raw sidecar record, positions are sent as strings of milliseconds
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_position: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_position: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    creation_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modification_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Value>,
+}
+
+impl Annotations {
+    fn from_records(asin: &str, records: Vec<Record>) -> Self {
+        let mut annotations = Annotations {
+            asin: asin.to_string(),
+            ..Default::default()
+        };
+        for record in records {
+            let position = |p: &Option<String>| p.as_deref().and_then(|p| p.trim().parse().ok());
+            let start_ms = position(&record.start_position).unwrap_or_default();
+            let end_ms = position(&record.end_position);
+            let id = record.annotation_id.clone().unwrap_or_default();
+            let created = record.creation_time.as_deref().and_then(parse_timestamp);
+            let text = record.text.clone().or_else(|| {
+                record
+                    .metadata
+                    .as_ref()
+                    .and_then(|m| m["note"].as_str())
+                    .map(String::from)
+            });
+
+            match record.kind.as_str() {
+                "audible.bookmark" => annotations.bookmarks.push(Bookmark {
+                    id,
+                    position_ms: start_ms,
+                    created,
+                }),
+                "audible.note" => annotations.notes.push(Note {
+                    id,
+                    start_ms,
+                    end_ms,
+                    text: text.unwrap_or_default(),
+                    created,
+                }),
+                "audible.clip" => annotations.clips.push(Clip {
+                    id,
+                    start_ms,
+                    end_ms: end_ms.unwrap_or(start_ms),
+                    text,
+                    created,
+                }),
+                "audible.last_heard" => annotations.last_heard_ms = Some(start_ms),
+                _ => {}
+            }
+        }
+        annotations.bookmarks.sort_by_key(|b| b.position_ms);
+        annotations.notes.sort_by_key(|n| n.start_ms);
+        annotations.clips.sort_by_key(|c| c.start_ms);
+        annotations
+    }
+}
+
+impl Record {
+    /// The time the record was created, as sent
+    fn created(&self) -> Option<DateTime<Utc>> {
+        self.creation_time.as_deref().and_then(parse_timestamp)
+    }
+}
+
+fn new_record(kind: &str, start_ms: u64, end_ms: u64, text: Option<&str>) -> Record {
+    let now = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+    Record {
+        kind: kind.to_string(),
+        annotation_id: Some(uuid::Uuid::new_v4().to_string()),
+        start_position: Some(start_ms.to_string()),
+        end_position: Some(end_ms.to_string()),
+        creation_time: Some(now.clone()),
+        last_modification_time: Some(now),
+        text: text.map(String::from),
+        metadata: text.map(|text| json!({ "note": text })),
+    }
+}
+
 impl Client {
     /// GET https://cde-ta-g7g.amazon.com/FionaCDEServiceEngine/sidecar
     /// Returns the clips, notes, and bookmarks of a book
@@ -12,7 +146,7 @@ impl Client {
     /// - `type` (string) – ["AUDI"]
     /// - `key` (string) – ASIN of the book
     pub async fn get_sidecar(&self, params: Option<Value>) -> Result<Value> {
-        let url = "https://cde-ta-g7g.amazon.com/FionaCDEServiceEngine/sidecar";
+        let url = SIDECAR_URL;
 
         let mut req = self.client.get(url);
         if let Some(params) = params {
@@ -24,4 +158,163 @@ impl Client {
         let json: Value = res.json().await?;
         Ok(json)
     }
+
+    /// POST https://cde-ta-g7g.amazon.com/FionaCDEServiceEngine/sidecar
+    /// Creates or deletes clips, notes, and bookmarks of a book
+    ///
+    /// Query Parameters:
+    /// - `type` (string) – ["AUDI"]
+    /// - `key` (string) – ASIN of the book
+    ///
+    /// Request JSON Object:
+    /// - `records` (list) – records as returned by GET, with an `action` of [create, delete]
+    pub async fn post_sidecar(&self, query: Value, params: Option<Value>) -> Result<Value> {
+        let url = SIDECAR_URL;
+
+        let mut req = self.client.post(url).query(&query);
+        if let Some(params) = params {
+            req = req.json(&params);
+        }
+        let req = req.build()?;
+
+        let res = self.send_request(req).await?;
+        json_or_null(res).await
+    }
+
+    /// Typed clips, notes and bookmarks of a book
+    pub async fn get_annotations(&self, asin: &str) -> Result<Annotations> {
+        let params = json! {{
+            "type": "AUDI",
+            "key": asin,
+        }};
+        let json = self.get_sidecar(Some(params)).await?;
+        let payload: Value = take_field(json, "payload")?;
+        let records: Vec<Record> = take_field(payload, "records")?;
+        Ok(Annotations::from_records(asin, records))
+    }
+
+    pub async fn add_bookmark(&self, asin: &str, position_ms: u64) -> Result<Bookmark> {
+        let record = new_record("audible.bookmark", position_ms, position_ms, None);
+        self.write_records(asin, "create", &[&record]).await?;
+        let created = record.created();
+        Ok(Bookmark {
+            id: record.annotation_id.unwrap_or_default(),
+            position_ms,
+            created,
+        })
+    }
+
+    pub async fn add_note(
+        &self,
+        asin: &str,
+        start_ms: u64,
+        end_ms: u64,
+        text: &str,
+    ) -> Result<Note> {
+        let record = new_record("audible.note", start_ms, end_ms, Some(text));
+        self.write_records(asin, "create", &[&record]).await?;
+        let created = record.created();
+        Ok(Note {
+            id: record.annotation_id.unwrap_or_default(),
+            start_ms,
+            end_ms: Some(end_ms),
+            text: text.to_string(),
+            created,
+        })
+    }
+
+    pub async fn add_clip(
+        &self,
+        asin: &str,
+        start_ms: u64,
+        end_ms: u64,
+        text: Option<&str>,
+    ) -> Result<Clip> {
+        let record = new_record("audible.clip", start_ms, end_ms, text);
+        self.write_records(asin, "create", &[&record]).await?;
+        let created = record.created();
+        Ok(Clip {
+            id: record.annotation_id.unwrap_or_default(),
+            start_ms,
+            end_ms,
+            text: text.map(String::from),
+            created,
+        })
+    }
+
+    /// Delete a bookmark, note or clip by its id
+    pub async fn delete_annotation(&self, asin: &str, annotation_id: &str) -> Result<()> {
+        let existing = self
+            .get_sidecar(Some(json!({"type": "AUDI", "key": asin})))
+            .await?;
+        let payload: Value = take_field(existing, "payload")?;
+        let records: Vec<Record> = take_field(payload, "records")?;
+        let record = records
+            .iter()
+            .find(|r| r.annotation_id.as_deref() == Some(annotation_id))
+            .ok_or_else(|| format!("No annotation {} for {}", annotation_id, asin))?;
+        self.write_records(asin, "delete", &[record]).await
+    }
+
+    async fn write_records(&self, asin: &str, action: &str, records: &[&Record]) -> Result<()> {
+        let records: Vec<Value> = records
+            .iter()
+            .map(|record| {
+                let mut record = serde_json::to_value(record)?;
+                record["action"] = json!(action);
+                Ok(record)
+            })
+            .collect::<Result<_>>()?;
+        let query = json! {{
+            "type": "AUDI",
+            "key": asin,
+        }};
+        let json = self
+            .post_sidecar(query, Some(json!({ "records": records })))
+            .await?;
+        match json["message"].as_str() {
+            Some(message) if json["payload"].is_null() => Err(message.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotations_from_records() {
+        let records: Vec<Record> = serde_json::from_value(json!([
+            {"type": "audible.last_heard", "startPosition": "5000"},
+            {"type": "audible.clip", "annotationId": "c1", "startPosition": "2000", "endPosition": "4000",
+             "creationTime": "2021-09-13 14:15:46.0", "metadata": {"note": "a quote"}},
+            {"type": "audible.bookmark", "annotationId": "b2", "startPosition": "3000"},
+            {"type": "audible.bookmark", "annotationId": "b1", "startPosition": "1000"},
+            {"type": "audible.note", "annotationId": "n1", "startPosition": "1500", "text": "remember this"}
+        ]))
+        .unwrap();
+        let annotations = Annotations::from_records("B00TEST123", records);
+
+        assert_eq!(annotations.last_heard_ms, Some(5000));
+        assert_eq!(annotations.bookmarks[0].id, "b1");
+        assert_eq!(annotations.notes[0].text, "remember this");
+        assert_eq!(annotations.clips[0].end_ms, 4000);
+        assert_eq!(annotations.clips[0].text.as_deref(), Some("a quote"));
+        assert!(annotations.clips[0].created.is_some());
+    }
+
+    #[test]
+    fn test_new_record() {
+        let record = new_record("audible.bookmark", 1000, 1000, None);
+        let json = serde_json::to_value(&record).unwrap();
+        assert!(json.get("text").is_none());
+        assert!(json.get("metadata").is_none());
+        assert_eq!(
+            record
+                .created()
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
+            record.creation_time
+        );
+    }
 }
@@ -1,10 +1,457 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 
-use super::Client;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::product::nullable;
+use super::response_groups::{join, StatsResponseGroup};
+use super::{format_timestamp, json_or_null, parse_timestamp, Client};
 use crate::Result;
 
+/// Most days `get_stats_aggregates` returns per request
+pub const MAX_DAILY_INTERVAL: u32 = 30;
+/// Most months `get_stats_aggregates` returns per request
+pub const MAX_MONTHLY_INTERVAL: u32 = 12;
+/// Number of statuses sent per `stats/status/finished` call
+const FINISHED_BATCH_SIZE: usize = 50;
+
+/// Parameters of [`Client::get_stats_aggregates`], validated as they are set
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsQuery {
+    daily: Option<(NaiveDate, u32)>,
+    monthly: Option<(NaiveDate, u32)>,
+    total: bool,
+    locale: Option<String>,
+    store: Option<String>,
+}
+
+impl StatsQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `days` (0 to 30) of daily listening from `start_date` (`YYYY-MM-DD`)
+    pub fn daily(self, start_date: &str, days: u32) -> Result<Self> {
+        let start = NaiveDate::parse_from_str(start_date, "%Y-%m-%d")
+            .ok()
+            .filter(|_| start_date.len() == 10)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid daily start date, expected YYYY-MM-DD: {}",
+                    start_date
+                )
+            })?;
+        self.daily_from(start, days)
+    }
+
+    pub fn daily_from(mut self, start: NaiveDate, days: u32) -> Result<Self> {
+        if days > MAX_DAILY_INTERVAL {
+            return Err(format!(
+                "Daily interval must be 0 to {} days: {}",
+                MAX_DAILY_INTERVAL, days
+            )
+            .into());
+        }
+        self.daily = Some((start, days));
+        Ok(self)
+    }
+
+    /// `months` (0 to 12) of monthly listening from `start_month` (`YYYY-MM`)
+    pub fn monthly(self, start_month: &str, months: u32) -> Result<Self> {
+        let start = NaiveDate::parse_from_str(&format!("{}-01", start_month), "%Y-%m-%d")
+            .ok()
+            .filter(|_| start_month.len() == 7)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid monthly start date, expected YYYY-MM: {}",
+                    start_month
+                )
+            })?;
+        self.monthly_from(start, months)
+    }
+
+    /// Monthly listening from the month of `start`
+    pub fn monthly_from(mut self, start: NaiveDate, months: u32) -> Result<Self> {
+        if months > MAX_MONTHLY_INTERVAL {
+            return Err(format!(
+                "Monthly interval must be 0 to {} months: {}",
+                MAX_MONTHLY_INTERVAL, months
+            )
+            .into());
+        }
+        self.monthly = Some((first_of_month(start), months));
+        Ok(self)
+    }
+
+    /// Include the all time total
+    pub fn total(mut self, total: bool) -> Self {
+        self.total = total;
+        self
+    }
+
+    pub fn locale(mut self, locale: &str) -> Self {
+        self.locale = Some(locale.to_string());
+        self
+    }
+
+    /// One of `AudibleForInstitutions`, `Audible`, `AmazonEnglish`, `Rodizio`
+    pub fn store(mut self, store: &str) -> Self {
+        self.store = Some(store.to_string());
+        self
+    }
+
+    pub fn to_params(&self) -> Value {
+        let mut params = Map::new();
+        if let Some((start, days)) = self.daily {
+            params.insert(
+                "daily_listening_interval_start_date".into(),
+                json!(start.format("%Y-%m-%d").to_string()),
+            );
+            params.insert(
+                "daily_listening_interval_duration".into(),
+                json!(days.to_string()),
+            );
+        }
+        if let Some((start, months)) = self.monthly {
+            params.insert(
+                "monthly_listening_interval_start_date".into(),
+                json!(start.format("%Y-%m").to_string()),
+            );
+            params.insert(
+                "monthly_listening_interval_duration".into(),
+                json!(months.to_string()),
+            );
+        }
+        if self.total {
+            params.insert(
+                "response_groups".into(),
+                json!(join(&[StatsResponseGroup::TotalListeningStats])),
+            );
+        }
+        if let Some(locale) = &self.locale {
+            params.insert("locale".into(), json!(locale));
+        }
+        if let Some(store) = &self.store {
+            params.insert("store".into(), json!(store));
+        }
+        Value::Object(params)
+    }
+}
+
+/// Listening time of a single day or month
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct IntervalStats {
+    /// The day, or the first day of the month
+    pub start: NaiveDate,
+    pub listening_ms: u64,
+    /// Listening time per asin, when the response breaks it down
+    pub asins: BTreeMap<String, u64>,
+}
+
+/// Typed response of [`Client::get_stats_aggregates`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ListeningStats {
+    pub daily: Vec<IntervalStats>,
+    pub monthly: Vec<IntervalStats>,
+    /// All time listening, with [`StatsQuery::total`]
+    pub total_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RawStats {
+    #[serde(default, deserialize_with = "nullable")]
+    aggregated_daily_listening_stats: Vec<RawInterval>,
+    #[serde(default, deserialize_with = "nullable")]
+    aggregated_monthly_listening_stats: Vec<RawInterval>,
+    #[serde(alias = "total_listening_stats")]
+    aggregated_total_listening_stats: Option<RawInterval>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RawInterval {
+    interval_identifier: Option<String>,
+    #[serde(default)]
+    aggregated_sum: u64,
+    #[serde(default, deserialize_with = "nullable", alias = "asin_listening_stats")]
+    aggregated_asin_stats: Vec<RawAsinStats>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct RawAsinStats {
+    asin: String,
+    #[serde(default)]
+    aggregated_sum: u64,
+}
+
+impl ListeningStats {
+    pub fn from_json(json: Value) -> Result<Self> {
+        if let Some(message) = json["message"].as_str() {
+            return Err(message.into());
+        }
+        let raw: RawStats = serde_json::from_value(json)?;
+        let intervals = |raw: Vec<RawInterval>| -> Vec<IntervalStats> {
+            raw.into_iter()
+                .filter_map(|interval| {
+                    let id = interval.interval_identifier?;
+                    let start = NaiveDate::parse_from_str(&id, "%Y-%m-%d")
+                        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", id), "%Y-%m-%d"))
+                        .ok()?;
+                    let asins = interval
+                        .aggregated_asin_stats
+                        .into_iter()
+                        .map(|a| (a.asin, a.aggregated_sum))
+                        .collect();
+                    Some(IntervalStats {
+                        start,
+                        listening_ms: interval.aggregated_sum,
+                        asins,
+                    })
+                })
+                .collect()
+        };
+        Ok(Self {
+            daily: intervals(raw.aggregated_daily_listening_stats),
+            monthly: intervals(raw.aggregated_monthly_listening_stats),
+            total_ms: raw
+                .aggregated_total_listening_stats
+                .map(|t| t.aggregated_sum),
+        })
+    }
+}
+
+/// Sum the listening time of each asin over the intervals
+pub fn listening_by_asin(intervals: &[IntervalStats]) -> HashMap<String, u64> {
+    let mut totals = HashMap::new();
+    for interval in intervals {
+        for (asin, ms) in &interval.asins {
+            *totals.entry(asin.clone()).or_default() += ms;
+        }
+    }
+    totals
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+/// One entry per expected start date, in order, with zero for the ones missing from `intervals`
+fn fill_series(starts: &[NaiveDate], intervals: Vec<IntervalStats>) -> Vec<IntervalStats> {
+    let mut by_start: HashMap<NaiveDate, IntervalStats> =
+        intervals.into_iter().map(|i| (i.start, i)).collect();
+    starts
+        .iter()
+        .map(|start| {
+            by_start.remove(start).unwrap_or_else(|| IntervalStats {
+                start: *start,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Whether a book is marked as finished, and when that was set
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FinishedStatus {
+    pub asin: String,
+    #[serde(rename = "is_marked_as_finished", default)]
+    pub finished: bool,
+    #[serde(rename = "event_timestamp", default, deserialize_with = "timestamp")]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// A page of [`FinishedStatus`] along with the token of the next one
+#[derive(Deserialize, Debug, Default)]
+struct FinishedStatusPage {
+    #[serde(default, deserialize_with = "nullable")]
+    mark_as_finished_status_list: Vec<FinishedStatus>,
+    continuation_token: Option<String>,
+}
+
+impl FinishedStatusPage {
+    fn from_json(json: Value) -> Result<Self> {
+        match json["message"].as_str() {
+            Some(message) if json["mark_as_finished_status_list"].is_null() => Err(message.into()),
+            _ => Ok(serde_json::from_value(json)?),
+        }
+    }
+}
+
+fn timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?
+        .as_deref()
+        .and_then(parse_timestamp))
+}
+
+/// The statuses set between `start` and `end`, with `finished_only` the latest of each asin if finished
+fn filter_finished(
+    statuses: Vec<FinishedStatus>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    finished_only: bool,
+) -> Vec<FinishedStatus> {
+    let mut statuses: Vec<FinishedStatus> = statuses
+        .into_iter()
+        .filter(|s| s.timestamp.is_some_and(|t| t >= start && t <= end))
+        .collect();
+    if finished_only {
+        statuses.sort_by_key(|s| s.timestamp);
+        let mut latest: Vec<FinishedStatus> = Vec::new();
+        for status in statuses.into_iter().rev() {
+            if !latest.iter().any(|s| s.asin == status.asin) {
+                latest.push(status);
+            }
+        }
+        latest.reverse();
+        latest.retain(|s| s.finished);
+        statuses = latest;
+    }
+    statuses
+}
+
 impl Client {
+    /// Typed listening stats for the query
+    pub async fn get_listening_stats(&self, query: &StatsQuery) -> Result<ListeningStats> {
+        let json = self.get_stats_aggregates(Some(query.to_params())).await?;
+        ListeningStats::from_json(json)
+    }
+
+    /// Daily listening from `start` to `end` inclusive, requested 30 days at a time.
+    ///
+    /// Days without listening are included with a time of zero.
+    pub async fn get_daily_listening(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<IntervalStats>> {
+        let days: Vec<NaiveDate> = start.iter_days().take_while(|d| *d <= end).collect();
+        let mut intervals = Vec::new();
+        for chunk in days.chunks(MAX_DAILY_INTERVAL as usize) {
+            let query = StatsQuery::new().daily_from(chunk[0], chunk.len() as u32)?;
+            intervals.extend(self.get_listening_stats(&query).await?.daily);
+        }
+        Ok(fill_series(&days, intervals))
+    }
+
+    /// Monthly listening for `months` months from the month of `start`, requested 12 months at a time.
+    ///
+    /// Months without listening are included with a time of zero.
+    pub async fn get_monthly_listening(
+        &self,
+        start: NaiveDate,
+        months: u32,
+    ) -> Result<Vec<IntervalStats>> {
+        let start = first_of_month(start);
+        let starts: Vec<NaiveDate> = (0..months)
+            .filter_map(|n| start.checked_add_months(Months::new(n)))
+            .collect();
+        let mut intervals = Vec::new();
+        for chunk in starts.chunks(MAX_MONTHLY_INTERVAL as usize) {
+            let query = StatsQuery::new().monthly_from(chunk[0], chunk.len() as u32)?;
+            intervals.extend(self.get_listening_stats(&query).await?.monthly);
+        }
+        Ok(fill_series(&starts, intervals))
+    }
+
+    /// The finished status of each asin, asins that were never marked are left out
+    pub async fn get_finished_status(&self, asins: &[&str]) -> Result<Vec<FinishedStatus>> {
+        let mut statuses = Vec::new();
+        for asin in asins {
+            let params = json! {{
+                "asin": asin,
+            }};
+            let json = self.get_stats_status_finished(Some(params)).await?;
+            let page = FinishedStatusPage::from_json(json)?;
+            statuses.extend(
+                page.mark_as_finished_status_list
+                    .into_iter()
+                    .filter(|s| s.asin == *asin),
+            );
+        }
+        Ok(statuses)
+    }
+
+    /// Mark a book as finished, or as not finished.
+    ///
+    /// The returned status is the one sent, see [`Client::set_finished_many`].
+    pub async fn set_finished(&self, asin: &str, finished: bool) -> Result<FinishedStatus> {
+        let mut statuses = self.set_finished_many(&[(asin, finished)]).await?;
+        statuses.pop().ok_or_else(|| "No status in response".into())
+    }
+
+    /// Mark many books as finished or not finished, 50 per request.
+    ///
+    /// The response doesn't echo the statuses, so the returned ones are those sent,
+    /// all stamped with the time of the call.
+    pub async fn set_finished_many(&self, asins: &[(&str, bool)]) -> Result<Vec<FinishedStatus>> {
+        let now = Utc::now();
+        let statuses: Vec<FinishedStatus> = asins
+            .iter()
+            .map(|(asin, finished)| FinishedStatus {
+                asin: asin.to_string(),
+                finished: *finished,
+                timestamp: Some(now),
+            })
+            .collect();
+        for batch in statuses.chunks(FINISHED_BATCH_SIZE) {
+            let list: Vec<Value> = batch
+                .iter()
+                .map(|status| {
+                    json!({
+                        "asin": status.asin,
+                        "is_marked_as_finished": status.finished,
+                        "event_timestamp": status.timestamp.as_ref().map(format_timestamp),
+                    })
+                })
+                .collect();
+            let params = json! {{
+                "mark_as_finished_status_list": list,
+            }};
+            let json = self.post_stats_status_finished(Some(params)).await?;
+            if let Some(message) = json["message"].as_str() {
+                return Err(message.into());
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// Statuses set between `start` and `end`, following `continuation_token` through every page.
+    ///
+    /// Statuses without a timestamp are left out. With `finished_only` only the latest status of
+    /// each asin within the range is considered, so books marked as not finished again are left out.
+    pub async fn get_finished_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        finished_only: bool,
+    ) -> Result<Vec<FinishedStatus>> {
+        let mut statuses = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let mut params = json! {{
+                "start_date": format_timestamp(&start),
+            }};
+            if let Some(token) = &continuation_token {
+                params["continuation_token"] = json!(token);
+            }
+            let json = self.post_stats_status_finished(Some(params)).await?;
+            let page = FinishedStatusPage::from_json(json)?;
+            let len = page.mark_as_finished_status_list.len();
+            statuses.extend(page.mark_as_finished_status_list);
+            match page.continuation_token {
+                Some(token) if len > 0 && continuation_token.as_ref() != Some(&token) => {
+                    continuation_token = Some(token)
+                }
+                _ => break,
+            }
+        }
+        Ok(filter_finished(statuses, start, end, finished_only))
+    }
+
     /// GET /1.0/stats/aggregates
     ///
     /// Query Parameters:
@@ -54,6 +501,8 @@ impl Client {
     /// - `start_date` (string)
     /// - `status` (string)
     /// - `continuation_token` (string)
+    /// - `mark_as_finished_status_list` (list) – to set statuses, objects of
+    ///   `asin`, `is_marked_as_finished` and `event_timestamp`
     pub async fn post_stats_status_finished(&self, params: Option<Value>) -> Result<Value> {
         let url = format!("{}/1.0/stats/status/finished", self.base_url);
 
@@ -111,7 +560,108 @@ impl Client {
         let req = req.build()?;
 
         let res = self.send_request(req).await?;
-        let json: Value = res.json().await?;
-        Ok(json)
+        json_or_null(res).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_stats_query() {
+        let query = StatsQuery::new()
+            .daily("2024-02-01", 30)
+            .unwrap()
+            .monthly("2024-01", 12)
+            .unwrap()
+            .total(true);
+        assert_eq!(
+            query.to_params(),
+            json!({
+                "daily_listening_interval_start_date": "2024-02-01",
+                "daily_listening_interval_duration": "30",
+                "monthly_listening_interval_start_date": "2024-01",
+                "monthly_listening_interval_duration": "12",
+                "response_groups": "total_listening_stats",
+            })
+        );
+
+        assert!(StatsQuery::new().daily("2024-02-01", 31).is_err());
+        assert!(StatsQuery::new().daily("2024-2-1x", 1).is_err());
+        assert!(StatsQuery::new().daily("2024-2-1", 1).is_err());
+        assert!(StatsQuery::new().daily("02024-02-01", 1).is_err());
+        assert!(StatsQuery::new().monthly("2024-01", 13).is_err());
+        assert!(StatsQuery::new().monthly("2024-01-05", 1).is_err());
+    }
+
+    #[test]
+    fn test_finished_status_page() {
+        let json = json!({
+            "mark_as_finished_status_list": [
+                {"asin": "B00TEST123", "is_marked_as_finished": true, "event_timestamp": "2024-03-01T10:00:00.000Z"},
+                {"asin": "B00TEST456", "is_marked_as_finished": false}
+            ],
+            "continuation_token": "abc"
+        });
+        let page = FinishedStatusPage::from_json(json).unwrap();
+        assert_eq!(page.continuation_token.as_deref(), Some("abc"));
+        assert!(page.mark_as_finished_status_list[0].finished);
+        assert_eq!(
+            page.mark_as_finished_status_list[0].timestamp,
+            parse_timestamp("2024-03-01T10:00:00Z")
+        );
+        assert!(FinishedStatusPage::from_json(json!({"message": "Invalid token"})).is_err());
+    }
+
+    #[test]
+    fn test_filter_finished() {
+        let status = |asin: &str, finished: bool, timestamp: Option<&str>| FinishedStatus {
+            asin: asin.into(),
+            finished,
+            timestamp: timestamp.and_then(parse_timestamp),
+        };
+        let statuses = vec![
+            status("A", true, Some("2024-03-01T10:00:00Z")),
+            status("A", false, Some("2024-03-02T10:00:00Z")),
+            status("B", false, Some("2024-03-01T10:00:00Z")),
+            status("B", true, Some("2024-03-03T10:00:00Z")),
+            status("C", true, None),
+            status("D", true, Some("2024-05-01T10:00:00Z")),
+        ];
+        let start = parse_timestamp("2024-03-01T00:00:00Z").unwrap();
+        let end = parse_timestamp("2024-04-01T00:00:00Z").unwrap();
+
+        let finished = filter_finished(statuses.clone(), start, end, true);
+        assert_eq!(finished, [status("B", true, Some("2024-03-03T10:00:00Z"))]);
+        assert_eq!(filter_finished(statuses, start, end, false).len(), 4);
+    }
+
+    #[test]
+    fn test_listening_stats() {
+        let json = json!({
+            "aggregated_daily_listening_stats": [
+                {"interval_identifier": "2024-02-02", "aggregated_sum": 60000,
+                 "aggregated_asin_stats": [{"asin": "B00TEST123", "aggregated_sum": 60000}]}
+            ],
+            "aggregated_monthly_listening_stats": [
+                {"interval_identifier": "2024-02", "aggregated_sum": 90000}
+            ],
+            "aggregated_total_listening_stats": {"aggregated_sum": 120000}
+        });
+        let stats = ListeningStats::from_json(json).unwrap();
+        assert_eq!(stats.monthly[0].start, date("2024-02-01"));
+        assert_eq!(stats.total_ms, Some(120000));
+        assert_eq!(listening_by_asin(&stats.daily)["B00TEST123"], 60000);
+
+        let days = [date("2024-02-01"), date("2024-02-02"), date("2024-02-03")];
+        let series = fill_series(&days, stats.daily);
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].listening_ms, 0);
+        assert_eq!(series[1].listening_ms, 60000);
     }
 }
@@ -1,10 +1,180 @@
 /// see API docs at https://audible.readthedocs.io/en/latest/misc/external_api.html
-use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
 
-use super::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use super::product::Product;
+use super::response_groups::{join, LibraryResponseGroup, WishlistResponseGroup};
+use super::{json_or_null, take_field, Client};
 use crate::Result;
 
+/// Most results `get_wishlist` returns per page
+const MAX_NUM_RESULTS: u64 = 50;
+
+/// Requests in flight at once for bulk operations
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// A product on the user's wishlist
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WishlistItem {
+    #[serde(flatten)]
+    pub product: Product,
+    pub added_timestamp: Option<String>,
+}
+
+/// Outcome of one asin of a bulk operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkResult {
+    pub asin: String,
+    pub result: std::result::Result<(), String>,
+}
+
+impl BulkResult {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
 impl Client {
+    /// Typed version of [`Client::get_wishlist`], fetching every page of results.
+    ///
+    /// `num_results` is clamped to 1 to 50 items per page.
+    pub async fn get_wishlist_items(&self, params: Option<Value>) -> Result<Vec<WishlistItem>> {
+        let mut params = params.unwrap_or_else(|| json!({}));
+        let num_results = params["num_results"]
+            .as_u64()
+            .unwrap_or(MAX_NUM_RESULTS)
+            .clamp(1, MAX_NUM_RESULTS);
+        params["num_results"] = json!(num_results);
+
+        let mut items = Vec::new();
+        for page in 0.. {
+            params["page"] = json!(page);
+            let json = self.get_wishlist(Some(params.clone())).await?;
+            let page_items: Vec<WishlistItem> = take_field(json, "products")?;
+            let len = page_items.len() as u64;
+            items.extend(page_items);
+            if len == 0 || len < num_results {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    pub async fn add_to_wishlist(&self, asin: &str) -> Result<()> {
+        let json = self.post_wishlist(Some(json!({ "asin": asin }))).await?;
+        match json["message"].as_str() {
+            Some(message) => Err(message.into()),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn remove_from_wishlist(&self, asin: &str) -> Result<()> {
+        let json = self.delete_from_wishlist(asin, None).await?;
+        match json["message"].as_str() {
+            Some(message) => Err(message.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Add every asin, `concurrency` at a time, returning the outcome of each in order
+    pub async fn add_many_to_wishlist(
+        &self,
+        asins: &[&str],
+        concurrency: usize,
+    ) -> Vec<BulkResult> {
+        self.bulk(asins, concurrency, false).await
+    }
+
+    /// Remove every asin, `concurrency` at a time, returning the outcome of each in order
+    pub async fn remove_many_from_wishlist(
+        &self,
+        asins: &[&str],
+        concurrency: usize,
+    ) -> Vec<BulkResult> {
+        self.bulk(asins, concurrency, true).await
+    }
+
+    /// Remove the wishlist items that are already in the library
+    pub async fn remove_owned_from_wishlist(&self, concurrency: usize) -> Result<Vec<BulkResult>> {
+        let params = json! {{
+            "response_groups": join(&[WishlistResponseGroup::ProductAttrs]),
+        }};
+        let wishlist = self.get_wishlist_items(Some(params)).await?;
+        let params = json! {{
+            "response_groups": join(&[LibraryResponseGroup::ProductAttrs]),
+        }};
+        let owned: HashSet<String> = self
+            .get_library_items(Some(params))
+            .await?
+            .into_iter()
+            .map(|item| item.product.asin)
+            .collect();
+        let asins: Vec<&str> = wishlist
+            .iter()
+            .map(|item| item.product.asin.as_str())
+            .filter(|asin| owned.contains(*asin))
+            .collect();
+        Ok(self.remove_many_from_wishlist(&asins, concurrency).await)
+    }
+
+    /// Asins of the library and the wishlist, e.g. to leave out of recommendations
+    pub async fn get_owned_and_wishlisted_asins(&self) -> Result<HashSet<String>> {
+        let params = json! {{
+            "response_groups": join(&[LibraryResponseGroup::ProductAttrs]),
+        }};
+        let library = self.get_library_items(Some(params)).await?;
+        let params = json! {{
+            "response_groups": join(&[WishlistResponseGroup::ProductAttrs]),
+        }};
+        let wishlist = self.get_wishlist_items(Some(params)).await?;
+        Ok(library
+            .into_iter()
+            .map(|item| item.product.asin)
+            .chain(wishlist.into_iter().map(|item| item.product.asin))
+            .collect())
+    }
+
+    async fn bulk(&self, asins: &[&str], concurrency: usize, remove: bool) -> Vec<BulkResult> {
+        let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = JoinSet::new();
+        for (n, asin) in asins.iter().enumerate() {
+            let client = self.clone();
+            let semaphore = semaphore.clone();
+            let asin = asin.to_string();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let result = match remove {
+                    true => client.remove_from_wishlist(&asin).await,
+                    false => client.add_to_wishlist(&asin).await,
+                };
+                let result = result.map_err(|e| e.to_string());
+                (n, BulkResult { asin, result })
+            });
+        }
+
+        let mut results: Vec<Option<BulkResult>> = vec![None; asins.len()];
+        while let Some(joined) = tasks.join_next().await {
+            if let Ok((n, result)) = joined {
+                results[n] = Some(result);
+            }
+        }
+        results
+            .into_iter()
+            .zip(asins)
+            .map(|(result, asin)| {
+                result.unwrap_or_else(|| BulkResult {
+                    asin: asin.to_string(),
+                    result: Err("Task failed".into()),
+                })
+            })
+            .collect()
+    }
+
     /// GET /1.0/wishlist
     ///
     /// Query Parameters:
@@ -45,8 +215,7 @@ impl Client {
         let req = req.build()?;
 
         let res = self.send_request(req).await?;
-        let json: Value = res.json().await?;
-        Ok(json)
+        json_or_null(res).await
     }
 
     /// DELETE /1.0/wishlist/(string:asin)
@@ -59,15 +228,14 @@ impl Client {
     pub async fn delete_from_wishlist(&self, asin: &str, params: Option<Value>) -> Result<Value> {
         let url = format!("{}/1.0/wishlist/{}", self.base_url, asin);
 
-        let mut req = self.client.post(url);
+        let mut req = self.client.delete(url);
         if let Some(params) = params {
             req = req.json(&params);
         }
         let req = req.build()?;
 
         let res = self.send_request(req).await?;
-        let json: Value = res.json().await?;
-        Ok(json)
+        json_or_null(res).await
     }
 }
 
@@ -0,0 +1,292 @@
+//! iCalendar feed of upcoming release dates
+//!
+//! Every event has a UID derived from its asin only, so importing a newer feed updates the
+//! events of an earlier import instead of adding them again.
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::library::LibraryItem;
+use crate::api::product::Product;
+use crate::api::response_groups::{join, LibraryResponseGroup, WishlistResponseGroup};
+use crate::api::wishlist::WishlistItem;
+use crate::api::Client;
+use crate::release_monitor::NewRelease;
+use crate::series::SeriesReport;
+use crate::Result;
+
+const PRODID: &str = "-//audible_api//Upcoming releases//EN";
+/// Domain part of every event UID
+const UID_DOMAIN: &str = "releases.audible-api";
+/// Longest line in octets, longer lines are folded
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Why a release is on the calendar
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseSource {
+    PreOrder,
+    Wishlist,
+    Series {
+        title: String,
+        sequence: Option<String>,
+    },
+    /// A new release of followed authors or narrators
+    Followed {
+        names: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Release {
+    pub asin: String,
+    pub title: String,
+    pub release_date: NaiveDate,
+    pub source: ReleaseSource,
+}
+
+impl Release {
+    fn description(&self) -> String {
+        match &self.source {
+            ReleaseSource::PreOrder => "Pre-ordered".to_string(),
+            ReleaseSource::Wishlist => "On the wishlist".to_string(),
+            ReleaseSource::Series { title, sequence } => match sequence {
+                Some(sequence) => format!("Book {} of {}", sequence, title),
+                None => format!("Part of {}", title),
+            },
+            ReleaseSource::Followed { names } => format!("New from {}", names.join(", ")),
+        }
+    }
+}
+
+/// Releases of the calendar, at most one per asin
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ReleaseCalendar {
+    pub name: String,
+    pub releases: Vec<Release>,
+}
+
+impl ReleaseCalendar {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            releases: Vec::new(),
+        }
+    }
+
+    /// Add the release unless one with the same asin was added before
+    pub fn add(&mut self, release: Release) {
+        if !self.releases.iter().any(|r| r.asin == release.asin) {
+            self.releases.push(release);
+        }
+    }
+
+    /// Library items released after `today`, i.e. pre-orders
+    pub fn add_library(&mut self, library: &[LibraryItem], today: NaiveDate) {
+        for item in library {
+            self.add_upcoming(&item.product, ReleaseSource::PreOrder, today);
+        }
+    }
+
+    /// Wishlist items released after `today`
+    pub fn add_wishlist(&mut self, wishlist: &[WishlistItem], today: NaiveDate) {
+        for item in wishlist {
+            self.add_upcoming(&item.product, ReleaseSource::Wishlist, today);
+        }
+    }
+
+    /// The upcoming volumes of the series
+    pub fn add_series(&mut self, reports: &[SeriesReport]) {
+        for report in reports {
+            for volume in &report.upcoming {
+                let Some(release_date) = volume.release_date.as_deref().and_then(parse_date) else {
+                    continue;
+                };
+                self.add(Release {
+                    asin: volume.asin.clone(),
+                    title: volume.title.clone().unwrap_or_else(|| volume.asin.clone()),
+                    release_date,
+                    source: ReleaseSource::Series {
+                        title: report.series.title.clone(),
+                        sequence: volume.sequence.clone(),
+                    },
+                });
+            }
+        }
+    }
+
+    /// The pre-orders among the new releases of followed contributors
+    pub fn add_new_releases(&mut self, releases: &[NewRelease]) {
+        for release in releases.iter().filter(|r| r.pre_order) {
+            let Some(release_date) = release.release_date.as_deref().and_then(parse_date) else {
+                continue;
+            };
+            self.add(Release {
+                asin: release.asin.clone(),
+                title: release
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| release.asin.clone()),
+                release_date,
+                source: ReleaseSource::Followed {
+                    names: release.followed.iter().map(|f| f.name.clone()).collect(),
+                },
+            });
+        }
+    }
+
+    fn add_upcoming(&mut self, product: &Product, source: ReleaseSource, today: NaiveDate) {
+        let Some(release_date) = product.release_date.as_deref().and_then(parse_date) else {
+            return;
+        };
+        if release_date > today {
+            self.add(Release {
+                asin: product.asin.clone(),
+                title: product
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| product.asin.clone()),
+                release_date,
+                source,
+            });
+        }
+    }
+
+    /// The calendar as an [RFC 5545](https://tools.ietf.org/html/rfc5545) feed of all-day events
+    pub fn to_ics(&self, now: DateTime<Utc>) -> String {
+        let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            format!("PRODID:{}", PRODID),
+            "CALSCALE:GREGORIAN".to_string(),
+            "METHOD:PUBLISH".to_string(),
+            format!("X-WR-CALNAME:{}", escape_text(&self.name)),
+        ];
+        let mut releases: Vec<&Release> = self.releases.iter().collect();
+        releases.sort_by(|a, b| (a.release_date, &a.asin).cmp(&(b.release_date, &b.asin)));
+        for release in releases {
+            let end = release.release_date + Duration::days(1);
+            lines.extend([
+                "BEGIN:VEVENT".to_string(),
+                format!("UID:{}@{}", release.asin, UID_DOMAIN),
+                format!("DTSTAMP:{}", stamp),
+                format!(
+                    "DTSTART;VALUE=DATE:{}",
+                    release.release_date.format("%Y%m%d")
+                ),
+                format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")),
+                format!("SUMMARY:{}", escape_text(&release.title)),
+                format!("DESCRIPTION:{}", escape_text(&release.description())),
+                "TRANSP:TRANSPARENT".to_string(),
+                "END:VEVENT".to_string(),
+            ]);
+        }
+        lines.push("END:VCALENDAR".to_string());
+
+        let mut out = String::new();
+        for line in &lines {
+            out.push_str(&fold(line));
+        }
+        out
+    }
+}
+
+/// Pre-orders of the library and upcoming wishlist items
+pub async fn fetch(client: &Client, name: &str) -> Result<ReleaseCalendar> {
+    let params = json! {{
+        "response_groups": join(&[LibraryResponseGroup::ProductAttrs]),
+        "include_pending": "true",
+    }};
+    let library = client.get_library_items(Some(params)).await?;
+    let params = json! {{
+        "response_groups": join(&[WishlistResponseGroup::ProductAttrs]),
+    }};
+    let wishlist = client.get_wishlist_items(Some(params)).await?;
+
+    let today = Utc::now().date_naive();
+    let mut calendar = ReleaseCalendar::new(name);
+    calendar.add_library(&library, today);
+    calendar.add_wishlist(&wishlist, today);
+    Ok(calendar)
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// The line ending in CRLF, split into continuation lines of at most 75 octets
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            out.push_str("\r\n ");
+            // the leading space counts towards the continuation line
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ics() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let product = |asin: &str, title: &str, release_date: &str| Product {
+            asin: asin.into(),
+            title: Some(title.into()),
+            release_date: Some(release_date.into()),
+            ..Default::default()
+        };
+        let library = [
+            LibraryItem {
+                product: product("B00PRE0001", "Later; Still, Coming", "2025-03-01"),
+                ..Default::default()
+            },
+            LibraryItem {
+                product: product("B00OLD0001", "Released", "2024-03-01"),
+                ..Default::default()
+            },
+        ];
+        let wishlist = [
+            WishlistItem {
+                product: product("B00PRE0001", "Duplicate", "2025-03-01"),
+                ..Default::default()
+            },
+            WishlistItem {
+                product: product("B00WISH001", &"Long ".repeat(20), "2025-02-01"),
+                ..Default::default()
+            },
+        ];
+        let mut calendar = ReleaseCalendar::new("Releases");
+        calendar.add_library(&library, today);
+        calendar.add_wishlist(&wishlist, today);
+        assert_eq!(calendar.releases.len(), 2);
+
+        let ics = calendar.to_ics(Utc::now());
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("UID:B00PRE0001@releases.audible-api\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20250301\r\nDTEND;VALUE=DATE:20250302\r\n"));
+        assert!(ics.contains("SUMMARY:Later\\; Still\\, Coming\r\n"));
+        assert!(ics.contains("DESCRIPTION:Pre-ordered\r\n"));
+        // the wishlist item is released first
+        assert!(ics.find("B00WISH001").unwrap() < ics.find("B00PRE0001").unwrap());
+        assert!(ics.split("\r\n").all(|line| line.len() <= MAX_LINE_LENGTH));
+    }
+}
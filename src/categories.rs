@@ -0,0 +1,292 @@
+//! The catalog category tree, and where the library falls within it
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::catalog::{Category, CategoryRoot};
+use crate::api::library::LibraryItem;
+use crate::api::Client;
+use crate::Result;
+
+/// Levels of categories requested at once when loading the tree
+pub const LEVELS_PER_REQUEST: u32 = 3;
+/// Separates the category names of a path, e.g. `Mystery, Thriller & Suspense > Thriller`
+pub const PATH_SEPARATOR: &str = " > ";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CategoryTree {
+    pub categories: Vec<Category>,
+}
+
+/// Library items within a category
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct GenreStats {
+    /// Names from the top level category down, joined by [`PATH_SEPARATOR`]
+    pub path: String,
+    /// `None` for categories of the library that aren't part of the tree
+    pub category_id: Option<String>,
+    pub items: usize,
+    pub runtime_length_min: u64,
+}
+
+impl CategoryTree {
+    /// Load the categories under `root`, `max_depth` levels deep.
+    ///
+    /// Levels past the first request are filled in one category at a time, every category
+    /// is requested once at most.
+    pub async fn load(client: &Client, root: CategoryRoot, max_depth: u32) -> Result<Self> {
+        let max_depth = max_depth.max(1);
+        let levels = LEVELS_PER_REQUEST.min(max_depth);
+        let mut tree = Self {
+            categories: client.get_categories(root, levels).await?,
+        };
+
+        let mut cache: HashMap<String, Vec<Category>> = HashMap::new();
+        let mut frontier: Vec<String> = tree
+            .at_depth(levels as usize)
+            .into_iter()
+            .filter(|c| c.children.is_empty())
+            .map(|c| c.id.clone())
+            .collect();
+        for _ in levels..max_depth {
+            let mut next = Vec::new();
+            for id in frontier {
+                let children = match cache.get(&id) {
+                    Some(children) => children.clone(),
+                    None => {
+                        let children = client.get_category(&id).await?.children;
+                        cache.insert(id.clone(), children.clone());
+                        children
+                    }
+                };
+                next.extend(
+                    children
+                        .iter()
+                        .filter(|c| c.children.is_empty())
+                        .map(|c| c.id.clone()),
+                );
+                attach(&mut tree.categories, &id, &children);
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        Ok(tree)
+    }
+
+    /// The tree saved at `path`, loading and saving it there when the file doesn't exist yet
+    pub async fn load_cached(
+        client: &Client,
+        root: CategoryRoot,
+        max_depth: u32,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Ok(serde_json::from_slice(&fs::read(path)?)?);
+        }
+        let tree = Self::load(client, root, max_depth).await?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&tree)?)?;
+        Ok(tree)
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Category> {
+        self.path(id).and_then(|path| path.last().copied())
+    }
+
+    /// The categories from the top level down to the one with the id
+    pub fn path(&self, id: &str) -> Option<Vec<&Category>> {
+        fn search<'a>(categories: &'a [Category], id: &str, path: &mut Vec<&'a Category>) -> bool {
+            for category in categories {
+                path.push(category);
+                if category.id == id || search(&category.children, id, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+        let mut path = Vec::new();
+        search(&self.categories, id, &mut path).then_some(path)
+    }
+
+    /// The category at the path of names joined by [`PATH_SEPARATOR`], ignoring case,
+    /// e.g. `Mystery, Thriller & Suspense > Thriller`
+    pub fn find_path(&self, path: &str) -> Option<&Category> {
+        let mut categories = &self.categories;
+        let mut found = None;
+        for name in path.split(PATH_SEPARATOR).map(str::trim) {
+            let category = categories
+                .iter()
+                .find(|c| c.name.trim().eq_ignore_ascii_case(name))?;
+            categories = &category.children;
+            found = Some(category);
+        }
+        found
+    }
+
+    /// Items and runtime of the library by category, cut off `depth` levels deep, most items first.
+    ///
+    /// An item counts once towards every category any of its category ladders leads to.
+    pub fn genre_stats(&self, library: &[LibraryItem], depth: usize) -> Vec<GenreStats> {
+        let depth = depth.max(1);
+        let mut stats: BTreeMap<String, GenreStats> = BTreeMap::new();
+        for item in library {
+            let mut paths = BTreeSet::new();
+            for ladder in &item.product.category_ladders {
+                let in_tree = ladder.ladder.iter().rev().find_map(|c| self.path(&c.id));
+                let (names, id): (Vec<&str>, Option<&str>) = match in_tree {
+                    Some(path) => {
+                        let path = &path[..path.len().min(depth)];
+                        (
+                            path.iter().map(|c| c.name.as_str()).collect(),
+                            path.last().map(|c| c.id.as_str()),
+                        )
+                    }
+                    None => {
+                        let ladder = &ladder.ladder[..ladder.ladder.len().min(depth)];
+                        (ladder.iter().map(|c| c.name.as_str()).collect(), None)
+                    }
+                };
+                if !names.is_empty() {
+                    paths.insert((names.join(PATH_SEPARATOR), id));
+                }
+            }
+            for (path, id) in paths {
+                let entry = stats.entry(path.clone()).or_insert_with(|| GenreStats {
+                    path,
+                    category_id: id.map(String::from),
+                    ..Default::default()
+                });
+                entry.items += 1;
+                entry.runtime_length_min += item.product.runtime_length_min.unwrap_or_default();
+            }
+        }
+        let mut stats: Vec<GenreStats> = stats.into_values().collect();
+        stats.sort_by(|a, b| b.items.cmp(&a.items).then_with(|| a.path.cmp(&b.path)));
+        stats
+    }
+
+    /// The categories `depth` levels deep, the top level being 1
+    fn at_depth(&self, depth: usize) -> Vec<&Category> {
+        let mut level: Vec<&Category> = self.categories.iter().collect();
+        for _ in 1..depth {
+            level = level.into_iter().flat_map(|c| &c.children).collect();
+        }
+        level
+    }
+}
+
+/// Set the children of every category with the id
+fn attach(categories: &mut [Category], id: &str, children: &[Category]) {
+    for category in categories {
+        if category.id == id {
+            category.children = children.to_vec();
+        } else {
+            attach(&mut category.children, id, children);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::product::{CategoryLadder, CategoryRef, Product};
+
+    fn category(id: &str, name: &str, children: Vec<Category>) -> Category {
+        Category {
+            id: id.into(),
+            name: name.into(),
+            children,
+        }
+    }
+
+    fn item(ids: &[(&str, &str)], runtime: u64) -> LibraryItem {
+        LibraryItem {
+            product: Product {
+                runtime_length_min: Some(runtime),
+                category_ladders: vec![CategoryLadder {
+                    root: Some("Genres".into()),
+                    ladder: ids
+                        .iter()
+                        .map(|(id, name)| CategoryRef {
+                            id: id.to_string(),
+                            name: name.to_string(),
+                        })
+                        .collect(),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_category_tree() {
+        let mut tree = CategoryTree {
+            categories: vec![
+                category(
+                    "1",
+                    "Mystery, Thriller & Suspense",
+                    vec![
+                        category("11", "Thriller", vec![]),
+                        category("12", "Crime", vec![]),
+                        category("13", "Cat->Mouse", vec![]),
+                    ],
+                ),
+                category("2", "Romance", vec![]),
+            ],
+        };
+        attach(
+            &mut tree.categories,
+            "11",
+            &[category("111", "Legal", vec![])],
+        );
+        assert_eq!(tree.at_depth(3).len(), 1);
+
+        let path: Vec<&str> = tree
+            .path("111")
+            .unwrap()
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect();
+        assert_eq!(path, ["1", "11", "111"]);
+        assert_eq!(tree.find("12").unwrap().name, "Crime");
+        assert_eq!(
+            tree.find_path("mystery, thriller & suspense > Thriller")
+                .unwrap()
+                .id,
+            "11"
+        );
+        assert_eq!(
+            tree.find_path("Mystery, Thriller & Suspense > Cat->Mouse")
+                .unwrap()
+                .id,
+            "13"
+        );
+        assert!(tree.find_path("Romance > Thriller").is_none());
+
+        let library = [
+            item(
+                &[("1", "Mystery"), ("11", "Thriller"), ("111", "Legal")],
+                600,
+            ),
+            item(&[("1", "Mystery"), ("12", "Crime")], 300),
+            item(&[("1", "Mystery"), ("11", "Thriller")], 100),
+            item(&[("9", "Sci-Fi")], 50),
+        ];
+        let stats = tree.genre_stats(&library, 2);
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].path, "Mystery, Thriller & Suspense > Thriller");
+        assert_eq!(stats[0].items, 2);
+        assert_eq!(stats[0].runtime_length_min, 700);
+        assert_eq!(stats[2].path, "Sci-Fi");
+        assert_eq!(stats[2].category_id, None);
+    }
+}
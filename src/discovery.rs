@@ -0,0 +1,238 @@
+//! Recommendations narrowed down to what is worth looking at
+//!
+//! Owned and wishlisted titles are always left out, along with anything on the local
+//! [`NotInterested`] list, a JSON file of asins and authors the user dismissed.
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::product::Product;
+use crate::api::response_groups::RecommendationsResponseGroup;
+use crate::api::Client;
+use crate::json_file::JsonFile;
+use crate::Result;
+
+/// Response groups needed to apply every filter
+pub const DISCOVERY_RESPONSE_GROUPS: &[RecommendationsResponseGroup] = &[
+    RecommendationsResponseGroup::Contributors,
+    RecommendationsResponseGroup::ProductAttrs,
+    RecommendationsResponseGroup::ProductPlans,
+];
+
+/// Conditions on the recommended products, text comparisons ignore case
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RecommendationFilter {
+    plus_only: bool,
+    languages: Vec<String>,
+    min_runtime_min: Option<u64>,
+    max_runtime_min: Option<u64>,
+}
+
+impl RecommendationFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only products included with membership
+    pub fn plus_only(mut self, plus_only: bool) -> Self {
+        self.plus_only = plus_only;
+        self
+    }
+
+    /// Allow the language, e.g. `english`. Any language is allowed until one is added
+    pub fn language(mut self, language: &str) -> Self {
+        self.languages.push(language.trim().to_string());
+        self
+    }
+
+    /// Runtime bounds in minutes, both inclusive
+    pub fn runtime(mut self, min: Option<u64>, max: Option<u64>) -> Result<Self> {
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(format!("Runtime minimum {} is above maximum {}", min, max).into());
+            }
+        }
+        self.min_runtime_min = min;
+        self.max_runtime_min = max;
+        Ok(self)
+    }
+
+    /// Products without a runtime or language only pass when that condition isn't set
+    pub fn matches(&self, product: &Product) -> bool {
+        if self.plus_only && !product.in_plus_catalog() {
+            return false;
+        }
+        if !self.languages.is_empty()
+            && !product.language.as_deref().is_some_and(|language| {
+                self.languages
+                    .iter()
+                    .any(|l| l.eq_ignore_ascii_case(language.trim()))
+            })
+        {
+            return false;
+        }
+        let runtime = product.runtime_length_min;
+        let at_least = self
+            .min_runtime_min
+            .is_none_or(|min| runtime.is_some_and(|r| r >= min));
+        let at_most = self
+            .max_runtime_min
+            .is_none_or(|max| runtime.is_some_and(|r| r <= max));
+        at_least && at_most
+    }
+}
+
+/// Asins and authors never to recommend, saved as a [`JsonFile`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NotInterested {
+    #[serde(skip)]
+    path: PathBuf,
+    asins: BTreeSet<String>,
+    /// Lower case
+    authors: BTreeSet<String>,
+}
+
+impl JsonFile for NotInterested {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+}
+
+impl NotInterested {
+    pub fn add_asin(&mut self, asin: &str) {
+        self.asins.insert(asin.to_string());
+    }
+
+    pub fn remove_asin(&mut self, asin: &str) -> bool {
+        self.asins.remove(asin)
+    }
+
+    pub fn add_author(&mut self, author: &str) {
+        self.authors.insert(author.trim().to_lowercase());
+    }
+
+    pub fn remove_author(&mut self, author: &str) -> bool {
+        self.authors.remove(&author.trim().to_lowercase())
+    }
+
+    pub fn asins(&self) -> impl Iterator<Item = &str> {
+        self.asins.iter().map(String::as_str)
+    }
+
+    pub fn authors(&self) -> impl Iterator<Item = &str> {
+        self.authors.iter().map(String::as_str)
+    }
+
+    /// The product or any of its authors was dismissed
+    pub fn suppresses(&self, product: &Product) -> bool {
+        self.asins.contains(&product.asin)
+            || product
+                .authors
+                .iter()
+                .any(|a| self.authors.contains(&a.name.trim().to_lowercase()))
+    }
+}
+
+/// The `products` neither `excluded`, e.g. owned or wishlisted, nor suppressed and matching the filter
+pub fn filter_recommendations(
+    products: Vec<Product>,
+    excluded: &HashSet<String>,
+    filter: &RecommendationFilter,
+    not_interested: &NotInterested,
+) -> Vec<Product> {
+    products
+        .into_iter()
+        .filter(|product| !excluded.contains(&product.asin))
+        .filter(|product| !not_interested.suppresses(product))
+        .filter(|product| filter.matches(product))
+        .collect()
+}
+
+/// The recommendations of the user after filtering
+pub async fn recommend(
+    client: &Client,
+    filter: &RecommendationFilter,
+    not_interested: &NotInterested,
+) -> Result<Vec<Product>> {
+    // the endpoint takes a single language, more have to be filtered locally
+    let language = match filter.languages.as_slice() {
+        [language] => Some(language.as_str()),
+        _ => None,
+    };
+    let products = client
+        .get_recommended_products(DISCOVERY_RESPONSE_GROUPS, language)
+        .await?;
+    let excluded = client.get_owned_and_wishlisted_asins().await?;
+    Ok(filter_recommendations(
+        products,
+        &excluded,
+        filter,
+        not_interested,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::product::{Contributor, Plan};
+
+    fn product(asin: &str, author: &str, language: &str, runtime: u64, plus: bool) -> Product {
+        Product {
+            asin: asin.into(),
+            authors: vec![Contributor {
+                asin: None,
+                name: author.into(),
+            }],
+            language: Some(language.into()),
+            runtime_length_min: Some(runtime),
+            plans: match plus {
+                true => vec![Plan {
+                    plan_name: "US Minerva".into(),
+                    ..Default::default()
+                }],
+                false => vec![],
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filter_recommendations() {
+        let products = vec![
+            product("A", "Jane Doe", "english", 600, true),
+            product("B", "Jane Doe", "english", 600, true),
+            product("C", "Dismissed Author", "english", 600, true),
+            product("D", "John Roe", "german", 600, true),
+            product("E", "John Roe", "English", 60, true),
+            product("F", "John Roe", "english", 600, false),
+            product("G", "John Roe", "english", 1200, true),
+            product("H", "John Roe", "english", 300, true),
+        ];
+        let excluded: HashSet<String> = ["A".to_string()].into();
+        let mut not_interested =
+            NotInterested::new(std::env::temp_dir().join("audible_api_not_interested.json"));
+        not_interested.add_author(" dismissed author");
+        not_interested.add_asin("H");
+        let filter = RecommendationFilter::new()
+            .plus_only(true)
+            .language("English")
+            .runtime(Some(120), Some(900))
+            .unwrap();
+        let asins: Vec<String> =
+            filter_recommendations(products, &excluded, &filter, &not_interested)
+                .into_iter()
+                .map(|p| p.asin)
+                .collect();
+        assert_eq!(asins, ["B"]);
+
+        assert!(RecommendationFilter::new()
+            .runtime(Some(10), Some(5))
+            .is_err());
+        assert!(not_interested.remove_asin("H"));
+    }
+}
@@ -0,0 +1,306 @@
+//! Report downloads and listening to Audible, so they count towards stats and badges
+//!
+//! The download events follow the example of `PUT /1.0/stats/events` in the
+//! [API docs](https://audible.readthedocs.io/en/latest/misc/external_api.html).
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::api::{format_timestamp, Client, USER_AGENT};
+use crate::Result;
+
+/// Number of events buffered by [`StatsReporter`] before they are sent
+pub const DEFAULT_BATCH_SIZE: usize = 25;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListeningMode {
+    #[default]
+    Offline,
+    Streaming,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioType {
+    #[default]
+    FullTitle,
+    Sample,
+}
+
+/// Fields shared by every event sent from a player, by default the crate's [`USER_AGENT`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EventContext {
+    pub country_code: String,
+    pub user_agent: String,
+    /// e.g. `audible_iPhone`
+    pub source: String,
+    /// IANA name, e.g. `Europe/Berlin`
+    pub local_timezone: String,
+    /// One of `AudibleForInstitutions`, `Audible`, `AmazonEnglish`, `Rodizio`
+    pub store: String,
+    pub listening_mode: ListeningMode,
+    pub audio_type: AudioType,
+    pub asin_owned: bool,
+}
+
+impl Default for EventContext {
+    fn default() -> Self {
+        Self {
+            country_code: "us".into(),
+            user_agent: USER_AGENT.into(),
+            source: env!("CARGO_PKG_NAME").into(),
+            local_timezone: "UTC".into(),
+            store: "Audible".into(),
+            listening_mode: ListeningMode::default(),
+            audio_type: AudioType::default(),
+            asin_owned: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum StatsEvent {
+    DownloadStart {
+        asin: String,
+        /// e.g. `AAX_44_128`
+        codec: String,
+        download_host: String,
+        request_id: String,
+        timestamp: DateTime<Utc>,
+    },
+    DownloadComplete {
+        asin: String,
+        codec: String,
+        download_host: String,
+        request_id: String,
+        timestamp: DateTime<Utc>,
+    },
+    /// Playback from `start_ms` to `end_ms` of the book.
+    ///
+    /// Experimental: the API docs have no example of this event, so its payload is unverified
+    /// and the server may ignore or reject it.
+    ListeningSession {
+        asin: String,
+        start_ms: u64,
+        end_ms: u64,
+        started: DateTime<Utc>,
+        ended: DateTime<Utc>,
+    },
+}
+
+impl StatsEvent {
+    pub fn asin(&self) -> &str {
+        match self {
+            StatsEvent::DownloadStart { asin, .. }
+            | StatsEvent::DownloadComplete { asin, .. }
+            | StatsEvent::ListeningSession { asin, .. } => asin,
+        }
+    }
+
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            StatsEvent::DownloadStart { .. } => "DownloadStart",
+            StatsEvent::DownloadComplete { .. } => "DownloadComplete",
+            StatsEvent::ListeningSession { .. } => "ListeningSession",
+        }
+    }
+
+    /// The entry of the `stats` list of [`Client::put_stats_events`]
+    pub fn to_json(&self, context: &EventContext) -> Value {
+        let (key, details, timestamp, delivery_type) = match self {
+            StatsEvent::DownloadStart {
+                codec,
+                download_host,
+                request_id,
+                timestamp,
+                ..
+            }
+            | StatsEvent::DownloadComplete {
+                codec,
+                download_host,
+                request_id,
+                timestamp,
+                ..
+            } => (
+                match self {
+                    StatsEvent::DownloadStart { .. } => "download_start",
+                    _ => "download_complete",
+                },
+                json!({
+                    "country_code": context.country_code,
+                    "download_host": download_host,
+                    "user_agent": context.user_agent,
+                    "request_id": request_id,
+                    "codec": codec,
+                    "source": context.source,
+                }),
+                timestamp,
+                "Download",
+            ),
+            StatsEvent::ListeningSession {
+                start_ms,
+                end_ms,
+                started,
+                ended,
+                ..
+            } => (
+                "listening_session",
+                json!({
+                    "start_position_ms": start_ms,
+                    "end_position_ms": end_ms,
+                    "start_timestamp": format_timestamp(started),
+                    "end_timestamp": format_timestamp(ended),
+                    "duration_ms": (*ended - *started).num_milliseconds().max(0),
+                    "user_agent": context.user_agent,
+                    "source": context.source,
+                }),
+                ended,
+                match context.listening_mode {
+                    ListeningMode::Offline => "Download",
+                    ListeningMode::Streaming => "Streaming",
+                },
+            ),
+        };
+
+        json!({
+            key: details,
+            "social_network_site": "Unknown",
+            "event_type": self.event_type(),
+            "listening_mode": context.listening_mode,
+            "local_timezone": context.local_timezone,
+            "asin_owned": context.asin_owned,
+            "playing_immersion_reading": false,
+            "audio_type": context.audio_type,
+            "event_timestamp": format_timestamp(timestamp),
+            "asin": self.asin(),
+            "store": context.store,
+            "delivery_type": delivery_type,
+        })
+    }
+}
+
+/// Buffers events and sends them in batches, e.g. from a player that reports every session
+#[derive(Debug)]
+pub struct StatsReporter<'a> {
+    client: &'a Client,
+    context: EventContext,
+    batch_size: usize,
+    buffer: Mutex<Vec<StatsEvent>>,
+}
+
+impl<'a> StatsReporter<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            context: EventContext::default(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            buffer: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn context(mut self, context: EventContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Number of events to buffer before sending them, at least 1
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Events waiting to be sent
+    pub fn pending(&self) -> usize {
+        self.buffer.lock().unwrap().len()
+    }
+
+    /// Buffer an event, sending the buffer once it holds a full batch
+    pub async fn record(&self, event: StatsEvent) -> Result<()> {
+        let full = {
+            let mut buffer = self.buffer.lock().unwrap();
+            buffer.push(event);
+            buffer.len() >= self.batch_size
+        };
+        if full {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Send every buffered event, returning how many were sent.
+    ///
+    /// Events of a batch that fails to send are kept for the next flush.
+    pub async fn flush(&self) -> Result<usize> {
+        let events = std::mem::take(&mut *self.buffer.lock().unwrap());
+        let mut sent = 0;
+        for (n, batch) in events.chunks(self.batch_size).enumerate() {
+            if let Err(err) = send_events(self.client, batch, &self.context).await {
+                let mut buffer = self.buffer.lock().unwrap();
+                let failed = events[n * self.batch_size..].iter().cloned();
+                buffer.splice(0..0, failed);
+                return Err(err);
+            }
+            sent += batch.len();
+        }
+        Ok(sent)
+    }
+}
+
+/// Send events right away, without buffering
+pub async fn send_events(
+    client: &Client,
+    events: &[StatsEvent],
+    context: &EventContext,
+) -> Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let stats: Vec<Value> = events.iter().map(|e| e.to_json(context)).collect();
+    let json = client
+        .put_stats_events(Some(json!({ "stats": stats })))
+        .await?;
+    match json["message"].as_str() {
+        Some(message) => Err(message.into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::parse_timestamp;
+
+    #[test]
+    fn test_to_json() {
+        let context = EventContext {
+            local_timezone: "Europe/Berlin".into(),
+            ..Default::default()
+        };
+        let event = StatsEvent::DownloadStart {
+            asin: "B00TEST123".into(),
+            codec: "AAX_44_128".into(),
+            download_host: "xxxxx.cloudfront.net".into(),
+            request_id: "abc".into(),
+            timestamp: parse_timestamp("2020-10-23T21:29:06.985Z").unwrap(),
+        };
+        let json = event.to_json(&context);
+        assert_eq!(json["event_type"], "DownloadStart");
+        assert_eq!(json["download_start"]["codec"], "AAX_44_128");
+        assert_eq!(json["listening_mode"], "Offline");
+        assert_eq!(json["event_timestamp"], "2020-10-23T21:29:06.985Z");
+        assert_eq!(json["local_timezone"], "Europe/Berlin");
+
+        let event = StatsEvent::ListeningSession {
+            asin: "B00TEST123".into(),
+            start_ms: 60_000,
+            end_ms: 120_000,
+            started: parse_timestamp("2024-01-01T10:00:00Z").unwrap(),
+            ended: parse_timestamp("2024-01-01T10:01:00Z").unwrap(),
+        };
+        let json = event.to_json(&context);
+        assert_eq!(json["listening_session"]["duration_ms"], 60_000);
+        assert_eq!(json["listening_session"]["end_position_ms"], 120_000);
+        assert_eq!(json["audio_type"], "FullTitle");
+    }
+}
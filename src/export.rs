@@ -0,0 +1,430 @@
+//! Export bookmarks, notes and clips to Markdown (plain or Obsidian flavoured) and JSON
+//!
+//! Annotations of a book are grouped by the chapter they fall in, and each book
+//! becomes one Markdown file with its metadata in the front matter.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::content::ChapterInfo;
+use crate::api::library::LibraryItem;
+use crate::api::response_groups::join;
+use crate::api::sidecar::Annotations;
+use crate::api::Client;
+use crate::naming::{NamingTemplate, PathRegistry, NAMING_RESPONSE_GROUPS};
+use crate::Result;
+
+/// Default layout of exported files
+pub const EXPORT_TEMPLATE: &str = "{author}/{title}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownStyle {
+    /// CommonMark, clips as block quotes
+    #[default]
+    Plain,
+    /// Contributors as `[[links]]`, tags in the front matter and clips as callouts
+    Obsidian,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Bookmark,
+    Note,
+    Clip,
+}
+
+/// A bookmark, note or clip
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub id: String,
+    pub start_ms: u64,
+    pub end_ms: Option<u64>,
+    pub text: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChapterEntries {
+    /// `None` for entries before the first chapter or in books without chapters
+    pub title: Option<String>,
+    pub start_ms: u64,
+    pub entries: Vec<Entry>,
+}
+
+/// The annotations of a book along with the metadata needed to export them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookAnnotations {
+    pub asin: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub authors: Vec<String>,
+    pub narrators: Vec<String>,
+    pub series: Option<String>,
+    pub series_sequence: Option<String>,
+    pub release_date: Option<String>,
+    pub chapters: Vec<ChapterEntries>,
+    /// Used to name the exported files
+    #[serde(skip)]
+    pub item: LibraryItem,
+}
+
+impl BookAnnotations {
+    pub fn new(item: &LibraryItem, chapter_info: &ChapterInfo, annotations: &Annotations) -> Self {
+        let product = &item.product;
+        let series = product.series.first();
+
+        let mut entries: Vec<Entry> = annotations
+            .bookmarks
+            .iter()
+            .map(|b| Entry {
+                kind: EntryKind::Bookmark,
+                id: b.id.clone(),
+                start_ms: b.position_ms,
+                end_ms: None,
+                text: None,
+                created: b.created,
+            })
+            .chain(annotations.notes.iter().map(|n| Entry {
+                kind: EntryKind::Note,
+                id: n.id.clone(),
+                start_ms: n.start_ms,
+                end_ms: n.end_ms,
+                text: Some(n.text.clone()),
+                created: n.created,
+            }))
+            .chain(annotations.clips.iter().map(|c| Entry {
+                kind: EntryKind::Clip,
+                id: c.id.clone(),
+                start_ms: c.start_ms,
+                end_ms: Some(c.end_ms),
+                text: c.text.clone(),
+                created: c.created,
+            }))
+            .collect();
+        entries.sort_by_key(|e| e.start_ms);
+
+        // the flattened list is in playback order, so the last chapter starting
+        // before an entry is the most specific one containing it
+        let flattened = chapter_info.flatten();
+        let mut chapters: Vec<ChapterEntries> = Vec::new();
+        for entry in entries {
+            let chapter = flattened
+                .iter()
+                .rev()
+                .find(|c| c.start_offset_ms <= entry.start_ms);
+            let title = chapter.map(|c| c.title.clone());
+            match chapters.last_mut() {
+                Some(last) if last.title == title => last.entries.push(entry),
+                _ => chapters.push(ChapterEntries {
+                    title,
+                    start_ms: chapter.map(|c| c.start_offset_ms).unwrap_or_default(),
+                    entries: vec![entry],
+                }),
+            }
+        }
+
+        Self {
+            asin: product.asin.clone(),
+            title: product
+                .title
+                .clone()
+                .unwrap_or_else(|| product.asin.clone()),
+            subtitle: product.subtitle.clone(),
+            authors: product
+                .author_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            narrators: product
+                .narrator_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            series: series.map(|s| s.title.clone()),
+            series_sequence: series.and_then(|s| s.sequence.clone()),
+            release_date: product.release_date.clone(),
+            chapters,
+            item: item.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chapters.is_empty()
+    }
+
+    pub fn to_markdown(&self, style: MarkdownStyle) -> String {
+        let obsidian = style == MarkdownStyle::Obsidian;
+        let contributors = |names: &[String]| -> Vec<String> {
+            names
+                .iter()
+                .map(|name| match obsidian {
+                    true => yaml_string(&format!("[[{}]]", name)),
+                    false => yaml_string(name),
+                })
+                .collect()
+        };
+
+        let mut out = String::from("---\n");
+        out.push_str(&format!("asin: {}\n", yaml_string(&self.asin)));
+        out.push_str(&format!("title: {}\n", yaml_string(&self.title)));
+        if let Some(subtitle) = &self.subtitle {
+            out.push_str(&format!("subtitle: {}\n", yaml_string(subtitle)));
+        }
+        out.push_str(&format!(
+            "author: [{}]\n",
+            contributors(&self.authors).join(", ")
+        ));
+        if !self.narrators.is_empty() {
+            out.push_str(&format!(
+                "narrator: [{}]\n",
+                contributors(&self.narrators).join(", ")
+            ));
+        }
+        if let Some(series) = &self.series {
+            out.push_str(&format!("series: {}\n", yaml_string(series)));
+        }
+        if let Some(sequence) = &self.series_sequence {
+            out.push_str(&format!("series_sequence: {}\n", yaml_string(sequence)));
+        }
+        if let Some(release_date) = &self.release_date {
+            out.push_str(&format!("release_date: {}\n", yaml_string(release_date)));
+        }
+        if obsidian {
+            out.push_str("tags: [audiobook]\n");
+        }
+        out.push_str("---\n\n");
+        out.push_str(&format!("# {}\n", self.title));
+
+        for chapter in &self.chapters {
+            let title = chapter.title.as_deref().unwrap_or("Notes");
+            out.push_str(&format!("\n## {}\n", title));
+            for entry in &chapter.entries {
+                out.push('\n');
+                out.push_str(&entry.to_markdown(obsidian));
+            }
+        }
+        out
+    }
+}
+
+impl Entry {
+    fn to_markdown(&self, obsidian: bool) -> String {
+        let position = match self.end_ms.filter(|end| *end > self.start_ms) {
+            Some(end_ms) => format!(
+                "{} – {}",
+                format_timestamp(self.start_ms),
+                format_timestamp(end_ms)
+            ),
+            None => format_timestamp(self.start_ms),
+        };
+        let created = self
+            .created
+            .map(|c| format!(" · {}", c.format("%Y-%m-%d")))
+            .unwrap_or_default();
+        let text = self
+            .text
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty());
+
+        match (self.kind, text) {
+            (EntryKind::Bookmark, _) => format!("- 🔖 Bookmark at `{}`{}\n", position, created),
+            (EntryKind::Note, text) => format!(
+                "- 📝 `{}`{}\n{}",
+                position,
+                created,
+                text.map(|t| indent(t, "  ")).unwrap_or_default()
+            ),
+            (EntryKind::Clip, text) if obsidian => format!(
+                "> [!quote] Clip `{}`{}\n{}",
+                position,
+                created,
+                text.map(|t| indent(t, "> ")).unwrap_or_default()
+            ),
+            (EntryKind::Clip, text) => format!(
+                "- ✂️ Clip `{}`{}\n{}",
+                position,
+                created,
+                text.map(|t| indent(t, "  > ")).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Gather the annotations, chapters and metadata of a library item
+pub async fn fetch_book_annotations(client: &Client, asin: &str) -> Result<BookAnnotations> {
+    let params = json! {{
+        "response_groups": join(NAMING_RESPONSE_GROUPS),
+    }};
+    let item = client.get_library_item(asin, Some(params)).await?;
+    let chapter_info = client.get_chapter_info(asin).await?;
+    let annotations = client.get_annotations(asin).await?;
+    Ok(BookAnnotations::new(&item, &chapter_info, &annotations))
+}
+
+/// Write one Markdown file per book into `dir`, named by `naming` or [`EXPORT_TEMPLATE`].
+///
+/// Books without any annotations are skipped. Returns the paths written.
+pub fn write_markdown(
+    books: &[BookAnnotations],
+    dir: impl AsRef<Path>,
+    naming: Option<&NamingTemplate>,
+    style: MarkdownStyle,
+) -> Result<Vec<PathBuf>> {
+    let default_naming;
+    let naming = match naming {
+        Some(naming) => naming,
+        None => {
+            default_naming = NamingTemplate::new(EXPORT_TEMPLATE, &[])?;
+            &default_naming
+        }
+    };
+
+    let mut registry = PathRegistry::new();
+    let mut written = Vec::new();
+    for book in books.iter().filter(|b| !b.is_empty()) {
+        let path = registry.claim(&naming.render(&book.item, "md"), &book.asin);
+        let path = dir.as_ref().join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, book.to_markdown(style))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Write the annotations of all books into a single JSON file
+pub fn write_json(books: &[BookAnnotations], path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(books)?)?;
+    Ok(())
+}
+
+/// `H:MM:SS`
+fn format_timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Double quoted YAML scalar, so any value stays on a single line
+fn yaml_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn indent(text: &str, prefix: &str) -> String {
+    text.lines()
+        .map(|line| format!("{}{}\n", prefix, line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::content::Chapter;
+    use crate::api::product::{Contributor, Product};
+    use crate::api::sidecar::{Bookmark, Clip, Note};
+
+    fn book() -> BookAnnotations {
+        let item = LibraryItem {
+            product: Product {
+                asin: "B00TEST123".into(),
+                title: Some("The \"Test\" Book".into()),
+                authors: vec![Contributor {
+                    asin: None,
+                    name: "Jane Doe".into(),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let chapter = |title: &str, start_offset_ms, length_ms| Chapter {
+            title: title.into(),
+            start_offset_ms,
+            length_ms,
+            chapters: Vec::new(),
+        };
+        let chapter_info = ChapterInfo {
+            chapters: vec![
+                chapter("Opening Credits", 0, 10_000),
+                chapter("Chapter 1", 10_000, 3_600_000),
+            ],
+            ..Default::default()
+        };
+        let annotations = Annotations {
+            asin: "B00TEST123".into(),
+            bookmarks: vec![Bookmark {
+                id: "b1".into(),
+                position_ms: 5_000,
+                created: None,
+            }],
+            notes: vec![Note {
+                id: "n1".into(),
+                start_ms: 3_723_000,
+                end_ms: None,
+                text: "Remember this".into(),
+                created: None,
+            }],
+            clips: vec![Clip {
+                id: "c1".into(),
+                start_ms: 20_000,
+                end_ms: 30_000,
+                text: Some("A quote".into()),
+                created: None,
+            }],
+            last_heard_ms: None,
+        };
+        BookAnnotations::new(&item, &chapter_info, &annotations)
+    }
+
+    #[test]
+    fn test_group_by_chapter() {
+        let book = book();
+        assert_eq!(book.chapters.len(), 2);
+        assert_eq!(book.chapters[0].title.as_deref(), Some("Opening Credits"));
+        assert_eq!(book.chapters[1].entries.len(), 2);
+        assert_eq!(book.chapters[1].entries[1].kind, EntryKind::Note);
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let markdown = book().to_markdown(MarkdownStyle::Obsidian);
+        assert!(markdown.starts_with("---\nasin: \"B00TEST123\"\n"));
+        assert!(markdown.contains("title: \"The \\\"Test\\\" Book\"\n"));
+        assert!(markdown.contains("author: [\"[[Jane Doe]]\"]\n"));
+        assert!(markdown.contains("\n## Chapter 1\n"));
+        assert!(markdown.contains("> [!quote] Clip `0:00:20 – 0:00:30`\n> A quote\n"));
+        assert!(markdown.contains("- 📝 `1:02:03`\n  Remember this\n"));
+
+        let markdown = book().to_markdown(MarkdownStyle::Plain);
+        assert!(markdown.contains("author: [\"Jane Doe\"]\n"));
+        assert!(markdown.contains("  > A quote\n"));
+
+        assert_eq!(
+            yaml_string("Line one\r\nLine\ttwo\u{7}"),
+            "\"Line one\\r\\nLine\\ttwo\\x07\""
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Local state kept in a pretty printed JSON file, e.g. the price history of
+//! [`PriceStore`](crate::price_watch::PriceStore)
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Result;
+
+/// A value saved to a JSON file of its own, the path itself isn't part of the JSON
+pub trait JsonFile: Serialize + DeserializeOwned + Default {
+    fn path(&self) -> &Path;
+
+    fn set_path(&mut self, path: PathBuf);
+
+    /// An empty value saved to `path`, replacing any file there
+    fn new(path: impl AsRef<Path>) -> Self {
+        let mut value = Self::default();
+        value.set_path(path.as_ref().to_path_buf());
+        value
+    }
+
+    /// Load the value at `path`, starting empty when the file doesn't exist yet
+    fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut value = match path.exists() {
+            true => serde_json::from_slice(&fs::read(path)?)?,
+            false => Self::default(),
+        };
+        value.set_path(path.to_path_buf());
+        Ok(value)
+    }
+
+    /// Write the value back to its file
+    fn save(&self) -> Result<()> {
+        let path = self.path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+    struct Counter {
+        #[serde(skip)]
+        path: PathBuf,
+        count: u64,
+    }
+
+    impl JsonFile for Counter {
+        fn path(&self) -> &Path {
+            &self.path
+        }
+
+        fn set_path(&mut self, path: PathBuf) {
+            self.path = path;
+        }
+    }
+
+    #[test]
+    fn test_json_file() {
+        let path = std::env::temp_dir()
+            .join("audible_api_json_file")
+            .join("counter.json");
+        let _ = fs::remove_file(&path);
+        let mut counter = Counter::open(&path).unwrap();
+        assert_eq!(counter, Counter::new(&path));
+
+        counter.count = 3;
+        counter.save().unwrap();
+        assert_eq!(Counter::open(&path).unwrap(), counter);
+        assert!(!fs::read_to_string(&path).unwrap().contains("path"));
+    }
+}
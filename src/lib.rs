@@ -1,4 +1,21 @@
 pub mod api;
 pub mod auth;
+pub mod calendar;
+pub mod categories;
+pub mod discovery;
+pub mod events;
+pub mod export;
+pub mod json_file;
+pub mod naming;
+pub mod positions;
+pub mod price_watch;
+pub mod release_monitor;
+pub mod reports;
+pub mod series;
+pub mod similar_graph;
+pub mod smart_collections;
+pub mod stream;
+pub mod tagging;
+pub mod wishlist_export;
 
 pub type Result<T, E = Box<dyn std::error::Error>> = core::result::Result<T, E>;
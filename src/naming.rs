@@ -0,0 +1,453 @@
+//! File naming templates for downloaded and exported content
+//!
+//! A template is a relative path with `{field}` placeholders, e.g.
+//! `{author}/{series}/[{series_sequence} - ]{title} ({asin})`.
+//! Text in `[...]` is only kept when every placeholder inside it has a value,
+//! and path components that end up empty are dropped.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::api::library::LibraryItem;
+use crate::api::response_groups::LibraryResponseGroup;
+use crate::Result;
+
+/// Response groups needed to fill in every field of a template
+pub const NAMING_RESPONSE_GROUPS: &[LibraryResponseGroup] = &[
+    LibraryResponseGroup::Contributors,
+    LibraryResponseGroup::ProductAttrs,
+    LibraryResponseGroup::ProductDesc,
+    LibraryResponseGroup::Series,
+    LibraryResponseGroup::CategoryLadders,
+];
+
+/// Fields available to templates, besides any passed with [`Fields::with`]
+pub const FIELDS: [&str; 15] = [
+    "asin",
+    "title",
+    "subtitle",
+    "author",
+    "authors",
+    "narrator",
+    "narrators",
+    "series",
+    "series_sequence",
+    "publisher",
+    "release_date",
+    "year",
+    "language",
+    "genre",
+    "purchase_date",
+];
+
+/// Windows device names that can't be used as a file name
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(String),
+    Optional(Vec<Token>),
+}
+
+#[derive(Debug, Clone)]
+pub struct NamingTemplate {
+    template: String,
+    tokens: Vec<Token>,
+    max_component_len: usize,
+}
+
+/// Values substituted into a template
+#[derive(Debug, Clone, Default)]
+pub struct Fields(BTreeMap<String, String>);
+
+impl Fields {
+    pub fn new(item: &LibraryItem) -> Self {
+        let product = &item.product;
+        let series = product.series.first();
+        let mut fields = BTreeMap::new();
+        let mut insert = |name: &str, value: Option<&str>| {
+            if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+                fields.insert(name.to_string(), value.to_string());
+            }
+        };
+        insert("asin", Some(&product.asin));
+        insert("title", product.title.as_deref());
+        insert("subtitle", product.subtitle.as_deref());
+        insert("author", product.author_names().first().copied());
+        insert("authors", Some(&product.author_names().join(", ")));
+        insert("narrator", product.narrator_names().first().copied());
+        insert("narrators", Some(&product.narrator_names().join(", ")));
+        insert("series", series.map(|s| s.title.as_str()));
+        insert(
+            "series_sequence",
+            series.and_then(|s| s.sequence.as_deref()),
+        );
+        insert("publisher", product.publisher_name.as_deref());
+        insert("release_date", product.release_date.as_deref());
+        insert(
+            "year",
+            product.release_date.as_deref().and_then(|d| d.get(..4)),
+        );
+        insert("language", product.language.as_deref());
+        insert("genre", product.genre());
+        insert(
+            "purchase_date",
+            item.purchase_date.as_deref().and_then(|d| d.get(..10)),
+        );
+        Self(fields)
+    }
+
+    /// Add or override a field, e.g. the `image_size` of a cover
+    pub fn with(mut self, name: &str, value: impl ToString) -> Self {
+        self.0.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+}
+
+impl NamingTemplate {
+    /// Parse a template, `extra_fields` are names that will be supplied with [`Fields::with`]
+    pub fn new(template: &str, extra_fields: &[&str]) -> Result<Self> {
+        let tokens = parse(template)?;
+
+        fn check(tokens: &[Token], extra_fields: &[&str]) -> Result<()> {
+            for token in tokens {
+                match token {
+                    Token::Field(name)
+                        if !FIELDS.contains(&name.as_str())
+                            && !extra_fields.contains(&name.as_str()) =>
+                    {
+                        return Err(format!("Unknown template field {{{}}}", name).into())
+                    }
+                    Token::Optional(tokens) => check(tokens, extra_fields)?,
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+        check(&tokens, extra_fields)?;
+
+        Ok(Self {
+            template: template.to_string(),
+            tokens,
+            max_component_len: 255,
+        })
+    }
+
+    /// Maximum length in bytes of every directory and file name, 255 by default
+    pub fn max_component_len(mut self, len: usize) -> Self {
+        self.max_component_len = len;
+        self
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Whether the template has a `{name}` placeholder, inside an optional section or not
+    pub fn uses_field(&self, name: &str) -> bool {
+        fn uses(tokens: &[Token], name: &str) -> bool {
+            tokens.iter().any(|token| match token {
+                Token::Field(field) => field == name,
+                Token::Optional(tokens) => uses(tokens, name),
+                Token::Literal(_) => false,
+            })
+        }
+        uses(&self.tokens, name)
+    }
+
+    /// Relative path for the item, with `extension` appended
+    pub fn render(&self, item: &LibraryItem, extension: &str) -> PathBuf {
+        self.render_fields(&Fields::new(item), extension)
+    }
+
+    pub fn render_fields(&self, fields: &Fields, extension: &str) -> PathBuf {
+        let rendered = render_tokens(&self.tokens, fields, false).unwrap_or_default();
+        let components: Vec<String> = rendered
+            .split('/')
+            .map(|c| clean_component(&sanitize(c)))
+            .filter(|c| !c.is_empty())
+            .collect();
+
+        let mut path = PathBuf::new();
+        let last = components.len().saturating_sub(1);
+        for (i, component) in components.iter().enumerate() {
+            if i == last {
+                let extension = extension.trim_start_matches('.');
+                let reserved = if extension.is_empty() {
+                    0
+                } else {
+                    extension.len() + 1
+                };
+                let stem = fit(component, self.max_component_len.saturating_sub(reserved));
+                match extension.is_empty() {
+                    true => path.push(stem),
+                    false => path.push(format!("{}.{}", stem, extension)),
+                }
+            } else {
+                path.push(fit(component, self.max_component_len));
+            }
+        }
+        path
+    }
+}
+
+/// Hands out paths, disambiguating items whose rendered paths collide by appending ` (2)`, ` (3)`, ...
+#[derive(Debug, Clone, Default)]
+pub struct PathRegistry {
+    claimed: HashMap<PathBuf, String>,
+}
+
+impl PathRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The path for `asin`, the same path is always returned for the same asin
+    pub fn claim(&mut self, path: &Path, asin: &str) -> PathBuf {
+        let mut candidate = path.to_path_buf();
+        let mut n = 1;
+        while let Some(owner) = self.claimed.get(&candidate) {
+            if owner == asin {
+                return candidate;
+            }
+            n += 1;
+            candidate = numbered(path, n);
+        }
+        self.claimed.insert(candidate.clone(), asin.to_string());
+        candidate
+    }
+}
+
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{} ({}).{}", stem, n, extension.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+fn parse(template: &str) -> Result<Vec<Token>> {
+    let mut stack: Vec<Vec<Token>> = vec![Vec::new()];
+    let mut literal = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if !closed || name.trim().is_empty() || name.contains('{') {
+                    return Err(format!("Invalid placeholder in template: {}", template).into());
+                }
+                let tokens = stack.last_mut().unwrap();
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(Token::Field(name.trim().to_string()));
+            }
+            '[' if stack.len() == 1 => {
+                if !literal.is_empty() {
+                    stack[0].push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                stack.push(Vec::new());
+            }
+            ']' if stack.len() == 2 => {
+                let mut tokens = stack.pop().unwrap();
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                stack[0].push(Token::Optional(tokens));
+            }
+            '[' | ']' => {
+                return Err(format!("Unbalanced brackets in template: {}", template).into())
+            }
+            c => literal.push(c),
+        }
+    }
+    if stack.len() != 1 {
+        return Err(format!("Unbalanced brackets in template: {}", template).into());
+    }
+    let mut tokens = stack.pop().unwrap();
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+/// Missing fields render as empty, except inside an optional section which is then dropped entirely
+fn render_tokens(tokens: &[Token], fields: &Fields, optional: bool) -> Option<String> {
+    let mut out = String::new();
+    for token in tokens {
+        match token {
+            Token::Literal(literal) => out.push_str(literal),
+            Token::Field(name) => match fields.get(name) {
+                Some(value) => out.push_str(&sanitize(value)),
+                None if optional => return None,
+                None => {}
+            },
+            Token::Optional(tokens) => {
+                if let Some(rendered) = render_tokens(tokens, fields, true) {
+                    out.push_str(&rendered)
+                }
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Replace characters that are invalid in file names on any common platform
+pub fn sanitize(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ':' => out.push_str(" -"),
+            '"' => out.push('\''),
+            '<' | '>' | '/' | '\\' | '|' | '?' | '*' => out.push('_'),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Trim separators left dangling by missing fields
+fn clean_component(component: &str) -> String {
+    component
+        .trim_start_matches(|c: char| c.is_whitespace() || c == '-' || c == '.' || c == '_')
+        .trim_end_matches(|c: char| c.is_whitespace() || c == '-' || c == '.')
+        .to_string()
+}
+
+/// Truncate to `max_len`, then avoid reserved names, which truncation may have produced
+fn fit(component: &str, max_len: usize) -> String {
+    let truncated = truncate(component, max_len);
+    let stem = truncated.split('.').next().unwrap_or_default();
+    match RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        true => format!("{}_", truncate(truncated, max_len.saturating_sub(1))),
+        false => truncated.to_string(),
+    }
+}
+
+fn truncate(value: &str, max_len: usize) -> &str {
+    if value.len() <= max_len {
+        return value;
+    }
+    let mut end = max_len;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value[..end].trim_end_matches(|c: char| c.is_whitespace() || c == '-' || c == '.' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::product::{Contributor, Product, Series};
+
+    fn item() -> LibraryItem {
+        LibraryItem {
+            product: Product {
+                asin: "B00TEST123".into(),
+                title: Some("Mistborn: The Final Empire".into()),
+                authors: vec![Contributor {
+                    asin: None,
+                    name: "Brandon Sanderson".into(),
+                }],
+                series: vec![Series {
+                    title: "Mistborn".into(),
+                    sequence: Some("1".into()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = NamingTemplate::new(
+            "{author}/{series}/[{series_sequence} - ]{title} ({asin})",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&item(), "m4b"),
+            PathBuf::from(
+                "Brandon Sanderson/Mistborn/1 - Mistborn - The Final Empire (B00TEST123).m4b"
+            )
+        );
+
+        let mut standalone = item();
+        standalone.product.series.clear();
+        assert_eq!(
+            template.render(&standalone, "m4b"),
+            PathBuf::from("Brandon Sanderson/Mistborn - The Final Empire (B00TEST123).m4b")
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(NamingTemplate::new("{unknown}", &[]).is_err());
+        assert!(NamingTemplate::new("{title", &[]).is_err());
+        assert!(NamingTemplate::new("[{title}", &[]).is_err());
+        assert!(NamingTemplate::new("cover_{image_size}", &["image_size"]).is_ok());
+    }
+
+    #[test]
+    fn test_truncate_and_sanitize() {
+        let template = NamingTemplate::new("{title}", &[])
+            .unwrap()
+            .max_component_len(12);
+        let mut item = item();
+        item.product.title = Some("a/b\\c?d*e<f>g|h".into());
+        assert_eq!(template.render(&item, "pdf"), PathBuf::from("a_b_c_d.pdf"));
+
+        item.product.title = Some("CON".into());
+        assert_eq!(template.render(&item, ""), PathBuf::from("CON_"));
+
+        let template = NamingTemplate::new("Books: {asin}\\<new>/[Part: ]{title}", &[]).unwrap();
+        assert_eq!(
+            template.render(&item, "pdf"),
+            PathBuf::from("Books - B00TEST123__new_/Part - CON.pdf")
+        );
+        assert!(template.uses_field("asin"));
+        assert!(!NamingTemplate::new("[{asin}]{title}", &[])
+            .unwrap()
+            .uses_field("author"));
+
+        // truncation can leave a reserved name behind
+        let short = NamingTemplate::new("{title}", &[])
+            .unwrap()
+            .max_component_len(3);
+        item.product.title = Some("Context".into());
+        assert_eq!(short.render(&item, ""), PathBuf::from("Co_"));
+    }
+
+    #[test]
+    fn test_path_registry() {
+        let mut registry = PathRegistry::new();
+        let path = Path::new("Author/Title.m4b");
+        assert_eq!(registry.claim(path, "A"), PathBuf::from("Author/Title.m4b"));
+        assert_eq!(
+            registry.claim(path, "B"),
+            PathBuf::from("Author/Title (2).m4b")
+        );
+        assert_eq!(registry.claim(path, "A"), PathBuf::from("Author/Title.m4b"));
+    }
+}
@@ -0,0 +1,284 @@
+//! Sync listening positions between Audible and a local player
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::api::response_groups::{join, ContentResponseGroup};
+use crate::api::{parse_timestamp, take_field, Client};
+use crate::Result;
+
+/// Number of asins requested per `lastpositions` call
+const BATCH_SIZE: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LastPosition {
+    pub asin: String,
+    pub position_ms: u64,
+    pub last_updated: Option<DateTime<Utc>>,
+}
+
+/// How to pick between the local and remote position when they differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The most recently updated position wins
+    NewestWins,
+    /// The position furthest into the book wins
+    FurthestWins,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncAction {
+    InSync,
+    /// The remote position should be applied to the local player
+    UpdateLocal(LastPosition),
+    /// The local position was (or should be) sent to Audible
+    UpdateRemote(LastPosition),
+}
+
+/// Reads and writes last positions, acquiring and caching the `acr` each write requires
+#[derive(Debug)]
+pub struct Positions<'a> {
+    client: &'a Client,
+    acrs: Mutex<HashMap<String, String>>,
+}
+
+impl<'a> Positions<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            acrs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Seed the `acr` cache, e.g. from a previous session
+    pub fn with_acrs(self, acrs: HashMap<String, String>) -> Self {
+        self.acrs.lock().unwrap().extend(acrs);
+        self
+    }
+
+    /// The cached `acr` of every asin, to persist between sessions
+    pub fn acrs(&self) -> HashMap<String, String> {
+        self.acrs.lock().unwrap().clone()
+    }
+
+    /// The `acr` of the book, requesting a license the first time it is needed
+    pub async fn acr(&self, asin: &str) -> Result<String> {
+        if let Some(acr) = self.acrs.lock().unwrap().get(asin) {
+            return Ok(acr.clone());
+        }
+        let params = json! {{
+            "consumption_type": "Download",
+            "quality": "High",
+            "response_groups": join(&[
+                ContentResponseGroup::ContentReference,
+                ContentResponseGroup::LastPositionHeard,
+            ]),
+            "supported_media_features": {
+                "drm_types": ["Adrm", "Mpeg"],
+            },
+        }};
+        let license = self.client.get_content_license(asin, params).await?;
+        let acr = license.acr.ok_or("License has no acr")?;
+        self.acrs
+            .lock()
+            .unwrap()
+            .insert(asin.to_string(), acr.clone());
+        Ok(acr)
+    }
+
+    /// Last positions of the asins that have one, batching the requests
+    pub async fn get(&self, asins: &[&str]) -> Result<Vec<LastPosition>> {
+        let mut positions = Vec::new();
+        for batch in asins.chunks(BATCH_SIZE) {
+            let params = json! {{
+                "asins": batch.join(","),
+            }};
+            let json = self
+                .client
+                .get_annotations_lastpositions(Some(params))
+                .await?;
+            positions.extend(parse_last_positions(json)?);
+        }
+        Ok(positions)
+    }
+
+    pub async fn put(&self, asin: &str, position_ms: u64) -> Result<()> {
+        let acr = self.acr(asin).await?;
+        let params = json! {{
+            "acr": acr,
+            "asin": asin,
+            "position_ms": position_ms,
+        }};
+        self.client
+            .put_lastpositions_asin(asin, Some(params))
+            .await?;
+        Ok(())
+    }
+
+    /// Start books over: rewind them to the beginning and mark them as not finished
+    pub async fn reset(&self, asins: &[&str]) -> Result<()> {
+        for asin in asins {
+            self.put(asin, 0).await?;
+        }
+        let statuses: Vec<(&str, bool)> = asins.iter().map(|asin| (*asin, false)).collect();
+        self.client.set_finished_many(&statuses).await?;
+        Ok(())
+    }
+
+    /// Reconcile the local player's positions with Audible, pushing local positions that win.
+    ///
+    /// Returns what happened for every local position; `UpdateLocal` ones are left to the caller to apply.
+    pub async fn sync(
+        &self,
+        local: &[LastPosition],
+        policy: ConflictPolicy,
+    ) -> Result<Vec<SyncAction>> {
+        let asins: Vec<&str> = local.iter().map(|p| p.asin.as_str()).collect();
+        let remote: HashMap<String, LastPosition> = self
+            .get(&asins)
+            .await?
+            .into_iter()
+            .map(|p| (p.asin.clone(), p))
+            .collect();
+
+        let mut actions = Vec::with_capacity(local.len());
+        for position in local {
+            let action = resolve(position, remote.get(&position.asin), policy);
+            if let SyncAction::UpdateRemote(position) = &action {
+                self.put(&position.asin, position.position_ms).await?;
+            }
+            actions.push(action);
+        }
+        Ok(actions)
+    }
+}
+
+/// Decide which of the two positions of a book should be kept
+pub fn resolve(
+    local: &LastPosition,
+    remote: Option<&LastPosition>,
+    policy: ConflictPolicy,
+) -> SyncAction {
+    let Some(remote) = remote else {
+        return SyncAction::UpdateRemote(local.clone());
+    };
+    if local.position_ms == remote.position_ms {
+        return SyncAction::InSync;
+    }
+    let local_wins = match policy {
+        ConflictPolicy::NewestWins => local.last_updated > remote.last_updated,
+        ConflictPolicy::FurthestWins => local.position_ms > remote.position_ms,
+    };
+    match local_wins {
+        true => SyncAction::UpdateRemote(local.clone()),
+        false => SyncAction::UpdateLocal(remote.clone()),
+    }
+}
+
+#[derive(Deserialize)]
+struct PositionAnnotation {
+    asin: Option<String>,
+    #[serde(default)]
+    last_position_heard: PositionHeard,
+}
+
+#[derive(Deserialize, Default)]
+struct PositionHeard {
+    /// `Exists` or `DoesNotExist`
+    status: Option<String>,
+    position_ms: Option<u64>,
+    last_updated: Option<String>,
+}
+
+/// Parse a `lastpositions` response, skipping books that have never been played
+pub fn parse_last_positions(json: Value) -> Result<Vec<LastPosition>> {
+    let annotations: Vec<PositionAnnotation> = take_field(json, "asin_last_position_heard_annots")?;
+    Ok(annotations
+        .into_iter()
+        .filter(|a| a.last_position_heard.status.as_deref() != Some("DoesNotExist"))
+        .filter_map(|a| {
+            let heard = a.last_position_heard;
+            Some(LastPosition {
+                asin: a.asin?,
+                position_ms: heard.position_ms?,
+                last_updated: heard.last_updated.as_deref().and_then(parse_timestamp),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(position_ms: u64, timestamp: &str) -> LastPosition {
+        LastPosition {
+            asin: "B00TEST123".into(),
+            position_ms,
+            last_updated: parse_timestamp(timestamp),
+        }
+    }
+
+    #[test]
+    fn test_parse_last_positions() {
+        let json = json!({
+            "asin_last_position_heard_annots": [
+                {
+                    "asin": "B00TEST123",
+                    "last_position_heard": {
+                        "last_updated": "2021-08-17 18:24:01.442",
+                        "position_ms": 123456,
+                        "status": "Exists"
+                    }
+                },
+                {
+                    "asin": "B00TEST456",
+                    "last_position_heard": { "status": "DoesNotExist" }
+                }
+            ]
+        });
+        let positions = parse_last_positions(json).unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].position_ms, 123456);
+        assert!(positions[0].last_updated.is_some());
+        assert!(parse_last_positions(json!({"message": "Forbidden"})).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let older_further = position(90_000, "2024-01-01T10:00:00Z");
+        let newer_behind = position(30_000, "2024-01-02 10:00:00.000");
+
+        assert_eq!(
+            resolve(
+                &newer_behind,
+                Some(&older_further),
+                ConflictPolicy::NewestWins
+            ),
+            SyncAction::UpdateRemote(newer_behind.clone())
+        );
+        assert_eq!(
+            resolve(
+                &newer_behind,
+                Some(&older_further),
+                ConflictPolicy::FurthestWins
+            ),
+            SyncAction::UpdateLocal(older_further.clone())
+        );
+        assert_eq!(
+            resolve(&older_further, None, ConflictPolicy::NewestWins),
+            SyncAction::UpdateRemote(older_further.clone())
+        );
+        assert_eq!(
+            resolve(
+                &older_further,
+                Some(&older_further),
+                ConflictPolicy::NewestWins
+            ),
+            SyncAction::InSync
+        );
+    }
+}
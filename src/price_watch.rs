@@ -0,0 +1,240 @@
+//! Track wishlist prices and Plus catalog inclusion over time
+//!
+//! Every check snapshots the wishlist into a [`PriceStore`] kept as a JSON file, and
+//! compares the new snapshot of each item with its previous one.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::product::Product;
+use crate::api::response_groups::{join, WishlistResponseGroup};
+use crate::api::Client;
+use crate::json_file::JsonFile;
+use crate::Result;
+
+/// Response groups needed for a snapshot
+pub const PRICE_RESPONSE_GROUPS: &[WishlistResponseGroup] = &[
+    WishlistResponseGroup::ProductAttrs,
+    WishlistResponseGroup::Price,
+    WishlistResponseGroup::ProductPlans,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PriceSnapshot {
+    pub asin: String,
+    pub title: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub list_price: Option<f64>,
+    /// The lowest price at the time, e.g. a sale or member price
+    pub price: Option<f64>,
+    pub currency: Option<String>,
+    pub in_plus_catalog: bool,
+}
+
+impl PriceSnapshot {
+    pub fn new(product: &Product, timestamp: DateTime<Utc>) -> Self {
+        let price = product.price.as_ref();
+        let current = price.and_then(|p| p.current());
+        Self {
+            asin: product.asin.clone(),
+            title: product.title.clone(),
+            timestamp,
+            list_price: price.and_then(|p| p.list_price.as_ref()).map(|a| a.base),
+            price: current.map(|a| a.base),
+            currency: current.and_then(|a| a.currency_code.clone()),
+            in_plus_catalog: product.in_plus_catalog(),
+        }
+    }
+
+    /// Same prices and membership, whatever the time and title
+    fn unchanged_from(&self, other: &PriceSnapshot) -> bool {
+        self.list_price == other.list_price
+            && self.price == other.price
+            && self.currency == other.currency
+            && self.in_plus_catalog == other.in_plus_catalog
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PriceAlert {
+    /// The price fell below the threshold since the previous snapshot
+    BelowThreshold {
+        snapshot: PriceSnapshot,
+        threshold: f64,
+    },
+    /// The price is lower than in the previous snapshot
+    Dropped {
+        snapshot: PriceSnapshot,
+        previous: f64,
+    },
+    /// The item was added to the catalog included with membership
+    FreeWithMembership { snapshot: PriceSnapshot },
+}
+
+impl PriceAlert {
+    pub fn snapshot(&self) -> &PriceSnapshot {
+        match self {
+            PriceAlert::BelowThreshold { snapshot, .. }
+            | PriceAlert::Dropped { snapshot, .. }
+            | PriceAlert::FreeWithMembership { snapshot } => snapshot,
+        }
+    }
+}
+
+/// Snapshots of every watched asin, oldest first, saved as a [`JsonFile`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PriceStore {
+    #[serde(skip)]
+    path: PathBuf,
+    history: BTreeMap<String, Vec<PriceSnapshot>>,
+}
+
+impl JsonFile for PriceStore {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+}
+
+impl PriceStore {
+    pub fn history(&self, asin: &str) -> &[PriceSnapshot] {
+        self.history
+            .get(asin)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn latest(&self, asin: &str) -> Option<&PriceSnapshot> {
+        self.history(asin).last()
+    }
+
+    pub fn asins(&self) -> impl Iterator<Item = &str> {
+        self.history.keys().map(String::as_str)
+    }
+
+    /// Add the snapshot, returning the alerts raised compared to the previous snapshot of the asin.
+    ///
+    /// Nothing is added when the prices and membership are the same as in the previous snapshot.
+    pub fn record(&mut self, snapshot: PriceSnapshot, threshold: Option<f64>) -> Vec<PriceAlert> {
+        let previous = self.latest(&snapshot.asin);
+        if previous.is_some_and(|p| snapshot.unchanged_from(p)) {
+            return Vec::new();
+        }
+        let alerts = compare(previous, &snapshot, threshold);
+        self.history
+            .entry(snapshot.asin.clone())
+            .or_default()
+            .push(snapshot);
+        alerts
+    }
+}
+
+/// The alerts raised by `snapshot`, with no previous snapshot only the threshold and membership are checked
+pub fn compare(
+    previous: Option<&PriceSnapshot>,
+    snapshot: &PriceSnapshot,
+    threshold: Option<f64>,
+) -> Vec<PriceAlert> {
+    let mut alerts = Vec::new();
+    let previous_price = previous.and_then(|p| p.price);
+
+    if let (Some(threshold), Some(price)) = (threshold, snapshot.price) {
+        if price < threshold && previous_price.is_none_or(|p| p >= threshold) {
+            alerts.push(PriceAlert::BelowThreshold {
+                snapshot: snapshot.clone(),
+                threshold,
+            });
+        }
+    }
+    if let (Some(previous), Some(price)) = (previous_price, snapshot.price) {
+        if price < previous {
+            alerts.push(PriceAlert::Dropped {
+                snapshot: snapshot.clone(),
+                previous,
+            });
+        }
+    }
+    if snapshot.in_plus_catalog && !previous.is_some_and(|p| p.in_plus_catalog) {
+        alerts.push(PriceAlert::FreeWithMembership {
+            snapshot: snapshot.clone(),
+        });
+    }
+    alerts
+}
+
+/// Snapshot the whole wishlist into `store` and save it, returning the alerts raised
+pub async fn check_wishlist(
+    client: &Client,
+    store: &mut PriceStore,
+    threshold: Option<f64>,
+) -> Result<Vec<PriceAlert>> {
+    let params = json! {{
+        "response_groups": join(PRICE_RESPONSE_GROUPS),
+    }};
+    let items = client.get_wishlist_items(Some(params)).await?;
+
+    let now = Utc::now();
+    let mut alerts = Vec::new();
+    for item in &items {
+        alerts.extend(store.record(PriceSnapshot::new(&item.product, now), threshold));
+    }
+    store.save()?;
+    Ok(alerts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(price: f64, in_plus_catalog: bool) -> PriceSnapshot {
+        PriceSnapshot {
+            asin: "B00TEST123".into(),
+            title: None,
+            timestamp: Utc::now(),
+            list_price: Some(24.95),
+            price: Some(price),
+            currency: Some("USD".into()),
+            in_plus_catalog,
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let product: Product = serde_json::from_value(json!({
+            "asin": "B00TEST123",
+            "price": {
+                "list_price": {"base": 24.95, "currency_code": "USD", "type": "list"},
+                "lowest_price": {"base": 9.99, "currency_code": "USD", "type": "sale"}
+            },
+            "plans": [{"plan_name": "US Minerva"}]
+        }))
+        .unwrap();
+        let snapshot = PriceSnapshot::new(&product, Utc::now());
+        assert_eq!(snapshot.list_price, Some(24.95));
+        assert_eq!(snapshot.price, Some(9.99));
+        assert!(snapshot.in_plus_catalog);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut store = PriceStore::new(std::env::temp_dir().join("audible_api_prices.json"));
+        assert!(store.record(snapshot(24.95, false), Some(10.0)).is_empty());
+
+        let alerts = store.record(snapshot(9.99, false), Some(10.0));
+        assert!(matches!(alerts[0], PriceAlert::BelowThreshold { .. }));
+        assert!(matches!(alerts[1], PriceAlert::Dropped { previous, .. } if previous == 24.95));
+
+        // still below the threshold, nothing new
+        assert!(store.record(snapshot(9.99, false), Some(10.0)).is_empty());
+
+        let alerts = store.record(snapshot(9.99, true), Some(10.0));
+        assert!(matches!(alerts[0], PriceAlert::FreeWithMembership { .. }));
+        assert_eq!(store.history("B00TEST123").len(), 3);
+    }
+}
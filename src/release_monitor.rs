@@ -0,0 +1,279 @@
+//! New releases and pre-orders of the authors and narrators of the library
+//!
+//! The time of the last check and every release already reported are kept in a
+//! [`MonitorState`] JSON file, so each check only reports what is new since the previous one.
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::catalog::{CatalogResponseGroup, CatalogSearch, SortBy};
+use crate::api::library::LibraryItem;
+use crate::api::product::Product;
+use crate::api::response_groups::{join, LibraryResponseGroup};
+use crate::api::Client;
+use crate::json_file::JsonFile;
+use crate::Result;
+
+/// How far back the first check looks
+pub const DEFAULT_LOOKBACK_DAYS: i64 = 90;
+/// Most releases looked at per contributor and check
+const MAX_RESULTS_PER_CONTRIBUTOR: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Author,
+    Narrator,
+}
+
+/// An author or narrator whose new releases are monitored
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Followed {
+    pub role: Role,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NewRelease {
+    pub asin: String,
+    pub title: Option<String>,
+    pub release_date: Option<String>,
+    /// Released after the day of the check, so it can only be pre-ordered
+    pub pre_order: bool,
+    /// The followed contributors of the release
+    pub followed: Vec<Followed>,
+}
+
+/// Saved as a [`JsonFile`] between checks
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MonitorState {
+    #[serde(skip)]
+    path: PathBuf,
+    pub last_check: Option<DateTime<Utc>>,
+    /// Asins already reported
+    pub seen: BTreeSet<String>,
+}
+
+impl JsonFile for MonitorState {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+}
+
+impl MonitorState {}
+
+/// Authors and narrators of at least `min_items` library items, most frequent first
+pub fn followed_contributors(library: &[LibraryItem], min_items: usize) -> Vec<Followed> {
+    let mut counts: BTreeMap<Followed, usize> = BTreeMap::new();
+    for item in library {
+        let product = &item.product;
+        let authors = product.authors.iter().map(|c| (Role::Author, c));
+        let narrators = product.narrators.iter().map(|c| (Role::Narrator, c));
+        let mut counted = HashSet::new();
+        for (role, contributor) in authors.chain(narrators) {
+            let followed = Followed {
+                role,
+                name: contributor.name.trim().to_string(),
+            };
+            if counted.insert(followed.clone()) {
+                *counts.entry(followed).or_default() += 1;
+            }
+        }
+    }
+    let mut followed: Vec<(Followed, usize)> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= min_items.max(1))
+        .collect();
+    followed.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    followed.into_iter().map(|(f, _)| f).collect()
+}
+
+/// The releases of `found` neither `excluded`, e.g. owned or wishlisted, nor `seen` yet, one per asin
+pub fn new_releases(
+    found: &[(Followed, Product)],
+    excluded: &HashSet<String>,
+    seen: &BTreeSet<String>,
+    today: NaiveDate,
+) -> Vec<NewRelease> {
+    let mut releases: Vec<NewRelease> = Vec::new();
+    for (followed, product) in found {
+        if excluded.contains(&product.asin) || seen.contains(&product.asin) {
+            continue;
+        }
+        if let Some(release) = releases.iter_mut().find(|r| r.asin == product.asin) {
+            if !release.followed.contains(followed) {
+                release.followed.push(followed.clone());
+            }
+            continue;
+        }
+        let release_date = product.release_date.clone();
+        let pre_order = release_date
+            .as_deref()
+            .and_then(|d| NaiveDate::parse_from_str(d.get(..10)?, "%Y-%m-%d").ok())
+            .is_some_and(|d| d > today);
+        releases.push(NewRelease {
+            asin: product.asin.clone(),
+            title: product.title.clone(),
+            release_date,
+            pre_order,
+            followed: vec![followed.clone()],
+        });
+    }
+    releases.sort_by(|a, b| b.release_date.cmp(&a.release_date));
+    releases
+}
+
+/// Releases of the `followed` contributors since the last check, updating and saving `state`
+pub async fn check(
+    client: &Client,
+    state: &mut MonitorState,
+    followed: &[Followed],
+) -> Result<Vec<NewRelease>> {
+    let now = Utc::now();
+    let since = state
+        .last_check
+        .unwrap_or(now - Duration::days(DEFAULT_LOOKBACK_DAYS));
+    let since = since.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let mut found = Vec::new();
+    for contributor in followed {
+        let search = CatalogSearch::new()
+            .sort_by(SortBy::ReleaseDateDesc)
+            .since(&since)
+            .response_groups(&[
+                CatalogResponseGroup::Contributors,
+                CatalogResponseGroup::ProductAttrs,
+            ]);
+        let search = match contributor.role {
+            Role::Author => search.author(&contributor.name),
+            Role::Narrator => search.narrator(&contributor.name),
+        };
+        let products = client
+            .search_catalog_all(&search, MAX_RESULTS_PER_CONTRIBUTOR)
+            .await?;
+        // the search matches names loosely, keep the exact contributor only
+        found.extend(
+            products
+                .into_iter()
+                .filter(|product| credits(product, contributor))
+                .map(|product| (contributor.clone(), product)),
+        );
+    }
+
+    let excluded = client.get_owned_and_wishlisted_asins().await?;
+    let releases = new_releases(&found, &excluded, &state.seen, now.date_naive());
+    state.seen.extend(releases.iter().map(|r| r.asin.clone()));
+    state.last_check = Some(now);
+    state.save()?;
+    Ok(releases)
+}
+
+/// Follow the contributors of at least `min_items` library items and check their releases
+pub async fn check_library(
+    client: &Client,
+    state: &mut MonitorState,
+    min_items: usize,
+) -> Result<Vec<NewRelease>> {
+    let params = json! {{
+        "response_groups": join(&[LibraryResponseGroup::Contributors]),
+    }};
+    let library = client.get_library_items(Some(params)).await?;
+    let followed = followed_contributors(&library, min_items);
+    check(client, state, &followed).await
+}
+
+fn credits(product: &Product, followed: &Followed) -> bool {
+    let contributors = match followed.role {
+        Role::Author => &product.authors,
+        Role::Narrator => &product.narrators,
+    };
+    contributors
+        .iter()
+        .any(|c| c.name.trim().eq_ignore_ascii_case(&followed.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::product::Contributor;
+
+    fn product(asin: &str, author: &str, narrator: &str, release_date: &str) -> Product {
+        let contributor = |name: &str| Contributor {
+            asin: None,
+            name: name.into(),
+        };
+        Product {
+            asin: asin.into(),
+            authors: vec![contributor(author)],
+            narrators: vec![contributor(narrator)],
+            release_date: Some(release_date.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_new_releases() {
+        let library: Vec<LibraryItem> = [
+            product("A", "Jane Doe", "Sam Reader", "2020-01-01"),
+            product("B", "Jane Doe", "Jane Doe", "2021-01-01"),
+            product("C", "John Roe", "Sam Reader", "2022-01-01"),
+        ]
+        .into_iter()
+        .map(|product| LibraryItem {
+            product,
+            ..Default::default()
+        })
+        .collect();
+        let jane = Followed {
+            role: Role::Author,
+            name: "Jane Doe".into(),
+        };
+        let sam = Followed {
+            role: Role::Narrator,
+            name: "Sam Reader".into(),
+        };
+        assert_eq!(
+            followed_contributors(&library, 2),
+            [jane.clone(), sam.clone()]
+        );
+
+        let found = [
+            (
+                jane.clone(),
+                product("A", "Jane Doe", "Sam Reader", "2020-01-01"),
+            ),
+            (
+                jane.clone(),
+                product("D", "Jane Doe", "Sam Reader", "2024-06-01"),
+            ),
+            (
+                sam.clone(),
+                product("D", "Jane Doe", "Sam Reader", "2024-06-01"),
+            ),
+            (
+                jane.clone(),
+                product("E", "Jane Doe", "Other", "2025-03-01"),
+            ),
+            (
+                sam.clone(),
+                product("F", "Other", "Sam Reader", "2024-01-01"),
+            ),
+        ];
+        let excluded: HashSet<String> = ["A".to_string()].into();
+        let seen: BTreeSet<String> = ["F".to_string()].into();
+        let today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let releases = new_releases(&found, &excluded, &seen, today);
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[0].asin, "E");
+        assert!(releases[0].pre_order);
+        assert_eq!(releases[1].followed, [jane, sam]);
+        assert!(!releases[1].pre_order);
+    }
+}
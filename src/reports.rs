@@ -0,0 +1,517 @@
+//! Year-in-review listening reports
+//!
+//! Combines the daily and monthly listening stats, the finished status of books and
+//! library metadata, and renders the result as JSON, Markdown or a standalone HTML page.
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::library::LibraryItem;
+use crate::api::parse_timestamp;
+use crate::api::response_groups::{join, LibraryResponseGroup};
+use crate::api::stats::{listening_by_asin, IntervalStats};
+use crate::api::Client;
+use crate::Result;
+
+/// Number of entries in each of the top lists
+pub const TOP_N: usize = 10;
+
+const REPORT_RESPONSE_GROUPS: &[LibraryResponseGroup] = &[
+    LibraryResponseGroup::Contributors,
+    LibraryResponseGroup::ProductAttrs,
+    LibraryResponseGroup::CategoryLadders,
+    LibraryResponseGroup::IsFinished,
+    LibraryResponseGroup::ListeningStatus,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FinishedBook {
+    pub asin: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// An author, narrator or genre with the books finished and time listened
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ranked {
+    pub name: String,
+    pub books_finished: usize,
+    pub listening_ms: u64,
+}
+
+/// Consecutive days with any listening
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct YearInReview {
+    pub year: i32,
+    pub listening_ms: u64,
+    pub days_listened: u32,
+    pub books_finished: Vec<FinishedBook>,
+    pub top_authors: Vec<Ranked>,
+    pub top_narrators: Vec<Ranked>,
+    pub top_genres: Vec<Ranked>,
+    pub longest_streak: Option<Streak>,
+    /// Twelve entries, January first
+    pub monthly_ms: Vec<u64>,
+    pub busiest_day: Option<(NaiveDate, u64)>,
+}
+
+impl YearInReview {
+    /// Build the report from already fetched data.
+    ///
+    /// `finished` are the asins marked as finished along with when, entries outside of `year` are ignored.
+    pub fn new(
+        year: i32,
+        daily: &[IntervalStats],
+        monthly: &[IntervalStats],
+        finished: &[(String, Option<DateTime<Utc>>)],
+        library: &[LibraryItem],
+    ) -> Self {
+        let daily: Vec<&IntervalStats> = daily.iter().filter(|d| d.start.year() == year).collect();
+        let mut monthly_ms = vec![0; 12];
+        for month in monthly.iter().filter(|m| m.start.year() == year) {
+            monthly_ms[month.start.month0() as usize] += month.listening_ms;
+        }
+        // fall back to the days when the monthly stats are missing
+        if monthly_ms.iter().all(|ms| *ms == 0) {
+            for day in &daily {
+                monthly_ms[day.start.month0() as usize] += day.listening_ms;
+            }
+        }
+
+        let items: HashMap<&str, &LibraryItem> = library
+            .iter()
+            .map(|item| (item.product.asin.as_str(), item))
+            .collect();
+        let mut seen = HashSet::new();
+        let mut books_finished: Vec<FinishedBook> = finished
+            .iter()
+            .filter(|(_, at)| at.is_none_or(|at| at.year() == year))
+            .filter(|(asin, _)| seen.insert(asin.clone()))
+            .map(|(asin, finished_at)| {
+                let product = items.get(asin.as_str()).map(|item| &item.product);
+                FinishedBook {
+                    asin: asin.clone(),
+                    title: product.and_then(|p| p.title.clone()),
+                    authors: product
+                        .map(|p| p.author_names().into_iter().map(String::from).collect())
+                        .unwrap_or_default(),
+                    finished_at: *finished_at,
+                }
+            })
+            .collect();
+        books_finished.sort_by_key(|b| b.finished_at);
+
+        let days: Vec<IntervalStats> = daily.iter().map(|d| (*d).clone()).collect();
+        let by_asin = listening_by_asin(&days);
+        let finished_asins: HashSet<&str> =
+            books_finished.iter().map(|b| b.asin.as_str()).collect();
+        let rank = |names: &dyn Fn(&LibraryItem) -> Vec<String>| {
+            let mut ranked: HashMap<String, Ranked> = HashMap::new();
+            for item in library {
+                let asin = item.product.asin.as_str();
+                let listening_ms = by_asin.get(asin).copied().unwrap_or_default();
+                let is_finished = finished_asins.contains(asin);
+                if listening_ms == 0 && !is_finished {
+                    continue;
+                }
+                for name in names(item) {
+                    let entry = ranked.entry(name.clone()).or_insert(Ranked {
+                        name,
+                        books_finished: 0,
+                        listening_ms: 0,
+                    });
+                    entry.books_finished += is_finished as usize;
+                    entry.listening_ms += listening_ms;
+                }
+            }
+            let mut ranked: Vec<Ranked> = ranked.into_values().collect();
+            ranked.sort_by(|a, b| {
+                (b.listening_ms, b.books_finished)
+                    .cmp(&(a.listening_ms, a.books_finished))
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            ranked.truncate(TOP_N);
+            ranked
+        };
+        let top_authors = rank(&|item| {
+            item.product
+                .author_names()
+                .into_iter()
+                .map(String::from)
+                .collect()
+        });
+        let top_narrators = rank(&|item| {
+            item.product
+                .narrator_names()
+                .into_iter()
+                .map(String::from)
+                .collect()
+        });
+        let top_genres = rank(&|item| item.product.genre().map(String::from).into_iter().collect());
+
+        Self {
+            year,
+            listening_ms: monthly_ms.iter().sum(),
+            days_listened: daily.iter().filter(|d| d.listening_ms > 0).count() as u32,
+            books_finished,
+            top_authors,
+            top_narrators,
+            top_genres,
+            longest_streak: longest_streak(&daily),
+            monthly_ms,
+            busiest_day: daily
+                .iter()
+                .filter(|d| d.listening_ms > 0)
+                .max_by_key(|d| (d.listening_ms, std::cmp::Reverse(d.start)))
+                .map(|d| (d.start, d.listening_ms)),
+        }
+    }
+
+    pub fn hours_listened(&self) -> f64 {
+        self.listening_ms as f64 / 3_600_000.0
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {} in review\n\n", self.year);
+        out.push_str(&format!(
+            "- **{:.1}** hours listened over **{}** days\n",
+            self.hours_listened(),
+            self.days_listened
+        ));
+        out.push_str(&format!(
+            "- **{}** books finished\n",
+            self.books_finished.len()
+        ));
+        if let Some(streak) = &self.longest_streak {
+            out.push_str(&format!(
+                "- Longest streak: **{}** days ({} to {})\n",
+                streak.days, streak.start, streak.end
+            ));
+        }
+        if let Some((day, ms)) = &self.busiest_day {
+            out.push_str(&format!(
+                "- Busiest day: {} ({})\n",
+                day,
+                format_duration(*ms)
+            ));
+        }
+
+        out.push_str("\n## Monthly listening\n\n| Month | Hours | |\n|---|---:|---|\n");
+        let max = self
+            .monthly_ms
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or_default()
+            .max(1);
+        for (month, ms) in self.monthly_ms.iter().enumerate() {
+            let bar = "█".repeat((ms * 20 / max) as usize);
+            out.push_str(&format!(
+                "| {} | {:.1} | {} |\n",
+                MONTHS[month],
+                *ms as f64 / 3_600_000.0,
+                bar
+            ));
+        }
+
+        for (heading, ranked) in self.top_lists() {
+            if ranked.is_empty() {
+                continue;
+            }
+            out.push_str(&format!(
+                "\n## {}\n\n| | Name | Finished | Hours |\n|---:|---|---:|---:|\n",
+                heading
+            ));
+            for (n, entry) in ranked.iter().enumerate() {
+                out.push_str(&format!(
+                    "| {} | {} | {} | {:.1} |\n",
+                    n + 1,
+                    entry.name.replace('|', "\\|"),
+                    entry.books_finished,
+                    entry.listening_ms as f64 / 3_600_000.0
+                ));
+            }
+        }
+
+        if !self.books_finished.is_empty() {
+            out.push_str("\n## Books finished\n\n");
+            for book in &self.books_finished {
+                let date = book
+                    .finished_at
+                    .map(|at| format!("{} – ", at.format("%Y-%m-%d")))
+                    .unwrap_or_default();
+                out.push_str(&format!(
+                    "- {}**{}**{}\n",
+                    date,
+                    book.title.as_deref().unwrap_or(&book.asin),
+                    by_line(&book.authors)
+                ));
+            }
+        }
+        out
+    }
+
+    /// A standalone page, styles inlined and no external resources
+    pub fn to_html(&self) -> String {
+        let mut body = format!("<h1>{} in review</h1>\n<ul class=\"summary\">\n", self.year);
+        body.push_str(&format!(
+            "<li><strong>{:.1}</strong> hours listened over <strong>{}</strong> days</li>\n",
+            self.hours_listened(),
+            self.days_listened
+        ));
+        body.push_str(&format!(
+            "<li><strong>{}</strong> books finished</li>\n",
+            self.books_finished.len()
+        ));
+        if let Some(streak) = &self.longest_streak {
+            body.push_str(&format!(
+                "<li>Longest streak: <strong>{}</strong> days ({} to {})</li>\n",
+                streak.days, streak.start, streak.end
+            ));
+        }
+        if let Some((day, ms)) = &self.busiest_day {
+            body.push_str(&format!(
+                "<li>Busiest day: {} ({})</li>\n",
+                day,
+                format_duration(*ms)
+            ));
+        }
+        body.push_str("</ul>\n<h2>Monthly listening</h2>\n<table class=\"chart\">\n");
+        let max = self
+            .monthly_ms
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or_default()
+            .max(1);
+        for (month, ms) in self.monthly_ms.iter().enumerate() {
+            body.push_str(&format!(
+                "<tr><th>{}</th><td><div class=\"bar\" style=\"width: {}%\"></div></td><td>{:.1} h</td></tr>\n",
+                MONTHS[month],
+                ms * 100 / max,
+                *ms as f64 / 3_600_000.0
+            ));
+        }
+        body.push_str("</table>\n");
+
+        for (heading, ranked) in self.top_lists() {
+            if ranked.is_empty() {
+                continue;
+            }
+            body.push_str(&format!(
+                "<h2>{}</h2>\n<table>\n<tr><th></th><th>Name</th><th>Finished</th><th>Hours</th></tr>\n",
+                heading
+            ));
+            for (n, entry) in ranked.iter().enumerate() {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td></tr>\n",
+                    n + 1,
+                    escape_html(&entry.name),
+                    entry.books_finished,
+                    entry.listening_ms as f64 / 3_600_000.0
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        if !self.books_finished.is_empty() {
+            body.push_str("<h2>Books finished</h2>\n<ol>\n");
+            for book in &self.books_finished {
+                body.push_str(&format!(
+                    "<li><strong>{}</strong>{}</li>\n",
+                    escape_html(book.title.as_deref().unwrap_or(&book.asin)),
+                    escape_html(&by_line(&book.authors))
+                ));
+            }
+            body.push_str("</ol>\n");
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{} in review</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            self.year, STYLE, body
+        )
+    }
+
+    fn top_lists(&self) -> [(&str, &Vec<Ranked>); 3] {
+        [
+            ("Top authors", &self.top_authors),
+            ("Top narrators", &self.top_narrators),
+            ("Top genres", &self.top_genres),
+        ]
+    }
+}
+
+/// Fetch the stats, finished books and library of `year` and build its report
+pub async fn year_in_review(client: &Client, year: i32) -> Result<YearInReview> {
+    let start = NaiveDate::from_ymd_opt(year, 1, 1).ok_or("Invalid year")?;
+    let end = NaiveDate::from_ymd_opt(year, 12, 31).ok_or("Invalid year")?;
+    let daily = client.get_daily_listening(start, end).await?;
+    let monthly = client.get_monthly_listening(start, 12).await?;
+
+    let params = json! {{
+        "response_groups": join(REPORT_RESPONSE_GROUPS),
+    }};
+    let library = client.get_library_items(Some(params)).await?;
+
+    let year_start = start.and_hms_opt(0, 0, 0).ok_or("Invalid year")?.and_utc();
+    let year_end = end.and_hms_opt(23, 59, 59).ok_or("Invalid year")?.and_utc();
+    let mut finished: Vec<(String, Option<DateTime<Utc>>)> = client
+        .get_finished_between(year_start, year_end, true)
+        .await?
+        .into_iter()
+        .map(|status| (status.asin, status.timestamp))
+        .collect();
+    // books finished on devices that don't report the status event
+    finished.extend(library.iter().filter_map(|item| {
+        let at = item
+            .listening_status
+            .as_ref()?
+            .finished_at_timestamp
+            .as_deref()
+            .and_then(parse_timestamp)?;
+        Some((item.product.asin.clone(), Some(at)))
+    }));
+
+    Ok(YearInReview::new(
+        year, &daily, &monthly, &finished, &library,
+    ))
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:48rem;margin:2rem auto;padding:0 1rem;color:#222}\
+table{border-collapse:collapse;width:100%;margin-bottom:1rem}th,td{padding:.25rem .5rem;text-align:left}\
+.chart td:nth-child(2){width:70%}.bar{background:#f7991c;height:1rem;border-radius:2px}";
+
+fn longest_streak(daily: &[&IntervalStats]) -> Option<Streak> {
+    let mut days: Vec<NaiveDate> = daily
+        .iter()
+        .filter(|d| d.listening_ms > 0)
+        .map(|d| d.start)
+        .collect();
+    days.sort();
+    days.dedup();
+
+    let mut best: Option<Streak> = None;
+    let mut current: Option<Streak> = None;
+    for day in days {
+        let streak = match current {
+            Some(streak) if streak.end.succ_opt() == Some(day) => Streak {
+                end: day,
+                days: streak.days + 1,
+                ..streak
+            },
+            _ => Streak {
+                start: day,
+                end: day,
+                days: 1,
+            },
+        };
+        if best.is_none_or(|b| streak.days > b.days) {
+            best = Some(streak);
+        }
+        current = Some(streak);
+    }
+    best
+}
+
+/// e.g. `2 h 05 min`
+fn format_duration(ms: u64) -> String {
+    let minutes = ms / 60_000;
+    format!("{} h {:02} min", minutes / 60, minutes % 60)
+}
+
+fn by_line(authors: &[String]) -> String {
+    match authors.is_empty() {
+        true => String::new(),
+        false => format!(" by {}", authors.join(", ")),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::product::{Contributor, Product};
+
+    fn day(date: &str, listening_ms: u64, asin: &str) -> IntervalStats {
+        IntervalStats {
+            start: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            listening_ms,
+            asins: [(asin.to_string(), listening_ms)].into(),
+        }
+    }
+
+    fn item(asin: &str, author: &str) -> LibraryItem {
+        LibraryItem {
+            product: Product {
+                asin: asin.into(),
+                title: Some(format!("Title {}", asin)),
+                authors: vec![Contributor {
+                    asin: None,
+                    name: author.into(),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_year_in_review() {
+        let daily = [
+            day("2023-12-31", 3_600_000, "A"),
+            day("2024-01-01", 3_600_000, "A"),
+            day("2024-01-02", 7_200_000, "B"),
+            day("2024-01-03", 0, "B"),
+            day("2024-03-01", 1_800_000, "B"),
+            day("2024-03-02", 1_800_000, "A"),
+            day("2024-03-03", 1_800_000, "A"),
+        ];
+        let finished = [
+            ("A".to_string(), parse_timestamp("2024-03-03T10:00:00Z")),
+            ("C".to_string(), parse_timestamp("2023-06-01T10:00:00Z")),
+        ];
+        let library = [item("A", "Ann"), item("B", "Bob"), item("C", "Cy")];
+        let report = YearInReview::new(2024, &daily, &[], &finished, &library);
+
+        assert_eq!(report.listening_ms, 16_200_000);
+        assert_eq!(report.monthly_ms[0], 10_800_000);
+        assert_eq!(report.days_listened, 5);
+        assert_eq!(report.books_finished.len(), 1);
+        assert_eq!(report.longest_streak.unwrap().days, 3);
+        assert_eq!(report.top_authors[0].name, "Bob");
+        assert_eq!(report.top_authors[1].books_finished, 1);
+        assert_eq!(report.busiest_day.unwrap().1, 7_200_000);
+
+        assert!(report
+            .to_markdown()
+            .contains("- **4.5** hours listened over **5** days\n"));
+        assert!(report
+            .to_html()
+            .contains("<li><strong>Title A</strong> by Ann</li>"));
+    }
+}
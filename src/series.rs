@@ -0,0 +1,296 @@
+//! Series in the library, the volumes missing from them and their upcoming releases
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::catalog::{CatalogResponseGroup, SimilarityType};
+use crate::api::library::LibraryItem;
+use crate::api::product::{Product, Series};
+use crate::api::response_groups::{join, LibraryResponseGroup};
+use crate::api::Client;
+use crate::Result;
+
+/// Response groups needed to group the library by series
+pub const SERIES_RESPONSE_GROUPS: &[LibraryResponseGroup] = &[
+    LibraryResponseGroup::ProductAttrs,
+    LibraryResponseGroup::Series,
+];
+
+/// Longest run of consecutive missing positions reported as gaps, longer runs are taken
+/// for jumps in the numbering, e.g. of series numbered by year
+pub const MAX_GAP_RUN: u32 = 10;
+
+/// Response groups requested for the volumes found in the catalog
+const VOLUME_RESPONSE_GROUPS: &[CatalogResponseGroup] = &[
+    CatalogResponseGroup::ProductAttrs,
+    CatalogResponseGroup::Contributors,
+    CatalogResponseGroup::Series,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub asin: String,
+    pub title: Option<String>,
+    /// Position within the series, e.g. `"2.5"` or `"1-3"` for a box set
+    pub sequence: Option<String>,
+    pub release_date: Option<String>,
+}
+
+impl Volume {
+    fn new(product: &Product, series: Option<&Series>) -> Self {
+        Self {
+            asin: product.asin.clone(),
+            title: product.title.clone(),
+            sequence: series.and_then(|s| s.sequence.clone()),
+            release_date: product.release_date.clone(),
+        }
+    }
+
+    /// The first and last position the volume covers, the same for anything but box sets
+    pub fn positions(&self) -> Option<(f64, f64)> {
+        let sequence = self.sequence.as_deref()?.trim();
+        let number = |s: &str| -> Option<f64> {
+            let end = s
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(s.len());
+            s[..end].parse().ok()
+        };
+        let first = number(sequence)?;
+        let last = sequence
+            .split_once('-')
+            .and_then(|(_, last)| number(last.trim()))
+            .unwrap_or(first);
+        Some((first, last.max(first)))
+    }
+
+    fn released_after(&self, date: NaiveDate) -> bool {
+        self.release_date
+            .as_deref()
+            .and_then(|d| NaiveDate::parse_from_str(d.get(..10)?, "%Y-%m-%d").ok())
+            .is_some_and(|d| d > date)
+    }
+}
+
+/// A series with the volumes of it in the library, in series order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OwnedSeries {
+    pub asin: Option<String>,
+    pub title: String,
+    pub owned: Vec<Volume>,
+}
+
+impl OwnedSeries {
+    fn includes(&self, series: &Series) -> bool {
+        match (&self.asin, &series.asin) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.title.eq_ignore_ascii_case(&series.title),
+        }
+    }
+
+    /// Whole positions below the highest owned one that no owned volume covers,
+    /// e.g. `[2]` when owning volumes 1 and 3.
+    ///
+    /// Runs of more than [`MAX_GAP_RUN`] missing positions are left out.
+    pub fn gaps(&self) -> Vec<u32> {
+        let mut ranges: Vec<(f64, f64)> = self.owned.iter().filter_map(Volume::positions).collect();
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut gaps = Vec::new();
+        let mut next = 1;
+        for (first, last) in ranges {
+            let missing = next..first.ceil() as u32;
+            if missing.len() as u32 <= MAX_GAP_RUN {
+                gaps.extend(missing);
+            }
+            next = next.max(last.floor() as u32 + 1);
+        }
+        gaps
+    }
+}
+
+/// What the catalog has of a series beyond the owned volumes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeriesReport {
+    pub series: OwnedSeries,
+    pub gaps: Vec<u32>,
+    /// Volumes already released but not in the library
+    pub missing: Vec<Volume>,
+    /// Volumes with a release date still to come
+    pub upcoming: Vec<Volume>,
+}
+
+impl SeriesReport {
+    /// Sort the `candidates` found in the catalog into missing and upcoming volumes
+    pub fn new(series: OwnedSeries, candidates: &[Product], today: NaiveDate) -> Self {
+        let owned: HashSet<&str> = series.owned.iter().map(|v| v.asin.as_str()).collect();
+        let mut seen = HashSet::new();
+        let (mut upcoming, mut missing): (Vec<Volume>, Vec<Volume>) = candidates
+            .iter()
+            .filter(|product| !owned.contains(product.asin.as_str()))
+            .filter(|product| seen.insert(product.asin.as_str()))
+            .filter_map(|product| {
+                // without the `series` group the similarity type is all there is to go by
+                match product.series.is_empty() {
+                    true => Some(Volume::new(product, None)),
+                    false => product
+                        .series
+                        .iter()
+                        .find(|s| series.includes(s))
+                        .map(|s| Volume::new(product, Some(s))),
+                }
+            })
+            .partition(|volume| volume.released_after(today));
+        sort_volumes(&mut missing);
+        sort_volumes(&mut upcoming);
+
+        Self {
+            gaps: series.gaps(),
+            series,
+            missing,
+            upcoming,
+        }
+    }
+}
+
+/// The series of the library, sorted by title
+pub fn group_by_series(library: &[LibraryItem]) -> Vec<OwnedSeries> {
+    let mut by_key: BTreeMap<(String, Option<String>), OwnedSeries> = BTreeMap::new();
+    for item in library {
+        for series in &item.product.series {
+            let key = (series.title.to_lowercase(), series.asin.clone());
+            by_key
+                .entry(key)
+                .or_insert_with(|| OwnedSeries {
+                    asin: series.asin.clone(),
+                    title: series.title.clone(),
+                    owned: Vec::new(),
+                })
+                .owned
+                .push(Volume::new(&item.product, Some(series)));
+        }
+    }
+    by_key
+        .into_values()
+        .map(|mut series| {
+            sort_volumes(&mut series.owned);
+            series
+        })
+        .collect()
+}
+
+/// Look up the volumes of the series in the catalog, starting from its last owned volume
+pub async fn check_series(
+    client: &Client,
+    series: OwnedSeries,
+    today: NaiveDate,
+) -> Result<SeriesReport> {
+    let Some(last) = series.owned.last() else {
+        return Ok(SeriesReport::new(series, &[], today));
+    };
+    let mut candidates = client
+        .get_similar(
+            &last.asin,
+            SimilarityType::NextInSameSeries,
+            VOLUME_RESPONSE_GROUPS,
+        )
+        .await?;
+    candidates.extend(
+        client
+            .get_similar(
+                &last.asin,
+                SimilarityType::InTheSameSeries,
+                VOLUME_RESPONSE_GROUPS,
+            )
+            .await?,
+    );
+    Ok(SeriesReport::new(series, &candidates, today))
+}
+
+/// A report of every series in the library
+pub async fn track(client: &Client) -> Result<Vec<SeriesReport>> {
+    let params = json! {{
+        "response_groups": join(SERIES_RESPONSE_GROUPS),
+    }};
+    let library = client.get_library_items(Some(params)).await?;
+    let today = Utc::now().date_naive();
+
+    let mut reports = Vec::new();
+    for series in group_by_series(&library) {
+        reports.push(check_series(client, series, today).await?);
+    }
+    Ok(reports)
+}
+
+/// Series order, volumes without a position last
+fn sort_volumes(volumes: &mut [Volume]) {
+    volumes.sort_by(|a, b| {
+        let position = |v: &Volume| v.positions().map_or(f64::MAX, |(first, _)| first);
+        position(a).total_cmp(&position(b))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(asin: &str, sequence: &str, release_date: &str) -> Product {
+        Product {
+            asin: asin.into(),
+            release_date: Some(release_date.into()),
+            series: vec![Series {
+                asin: Some("SERIES".into()),
+                title: "The Series".into(),
+                sequence: Some(sequence.into()),
+                url: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_series_report() {
+        let library: Vec<LibraryItem> = [("C", "4"), ("A", "1-2"), ("B", "5.5")]
+            .into_iter()
+            .map(|(asin, sequence)| LibraryItem {
+                product: product(asin, sequence, "2020-01-01"),
+                ..Default::default()
+            })
+            .collect();
+        let series = group_by_series(&library);
+        assert_eq!(series.len(), 1);
+        let owned: Vec<&str> = series[0].owned.iter().map(|v| v.asin.as_str()).collect();
+        assert_eq!(owned, ["A", "C", "B"]);
+        assert_eq!(series[0].gaps(), [3, 5]);
+        let by_year = OwnedSeries {
+            asin: None,
+            title: "Annual".into(),
+            owned: ["1", "2019", "2021"]
+                .into_iter()
+                .map(|sequence| Volume {
+                    asin: sequence.into(),
+                    title: None,
+                    sequence: Some(sequence.into()),
+                    release_date: None,
+                })
+                .collect(),
+        };
+        assert_eq!(by_year.gaps(), [2020]);
+
+        let mut other = product("X", "1", "2020-01-01");
+        other.series[0].asin = Some("OTHER".into());
+        let candidates = [
+            product("A", "1-2", "2020-01-01"),
+            product("E", "7", "2031-05-01"),
+            product("D", "3", "2021-01-01"),
+            product("D", "3", "2021-01-01"),
+            other,
+        ];
+        let today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let report = SeriesReport::new(series[0].clone(), &candidates, today);
+        assert_eq!(report.gaps, [3, 5]);
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].asin, "D");
+        assert_eq!(report.upcoming[0].asin, "E");
+    }
+}
@@ -0,0 +1,344 @@
+//! Explore the products related to a product, hop by hop, and export them as a graph
+//!
+//! Each hop looks up the similar products of every product found by the previous one, so the
+//! number of requests grows quickly; [`Explorer`] caps the depth and the number of products,
+//! caches every lookup and keeps a minimum delay between requests.
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::time::{sleep_until, Instant};
+
+use crate::api::catalog::{CatalogResponseGroup, SimilarityType};
+use crate::api::product::Product;
+use crate::api::response_groups::join;
+use crate::api::Client;
+use crate::Result;
+
+/// Hops from the starting product explored by default
+pub const DEFAULT_MAX_DEPTH: usize = 2;
+pub const DEFAULT_MAX_NODES: usize = 200;
+/// Minimum time between two requests by default
+pub const DEFAULT_DELAY: Duration = Duration::from_millis(500);
+
+const NODE_RESPONSE_GROUPS: &[CatalogResponseGroup] = &[
+    CatalogResponseGroup::ProductAttrs,
+    CatalogResponseGroup::Contributors,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Node {
+    pub asin: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    /// Hops from the starting product
+    pub depth: usize,
+}
+
+impl Node {
+    fn new(product: &Product, depth: usize) -> Self {
+        Self {
+            asin: product.asin.clone(),
+            title: product.title.clone(),
+            authors: product
+                .author_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            depth,
+        }
+    }
+
+    fn label(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.asin)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub similarity: String,
+}
+
+/// Products keyed by asin and the similarities found between them
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SimilarGraph {
+    pub root: String,
+    pub nodes: BTreeMap<String, Node>,
+    pub edges: BTreeSet<Edge>,
+}
+
+impl SimilarGraph {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Graphviz source, labelling nodes with their title and edges with their similarity type
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = String::from("digraph similar {\n");
+        for node in self.nodes.values() {
+            let shape = match node.asin == self.root {
+                true => ", shape=box",
+                false => "",
+            };
+            out.push_str(&format!(
+                "  {} [label={}{}];\n",
+                quote(&node.asin),
+                quote(node.label()),
+                shape
+            ));
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "  {} -> {} [label={}];\n",
+                quote(&edge.from),
+                quote(&edge.to),
+                quote(&edge.similarity)
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"title\" for=\"node\" attr.name=\"title\" attr.type=\"string\"/>\n",
+            "  <key id=\"authors\" for=\"node\" attr.name=\"authors\" attr.type=\"string\"/>\n",
+            "  <key id=\"depth\" for=\"node\" attr.name=\"depth\" attr.type=\"int\"/>\n",
+            "  <key id=\"similarity\" for=\"edge\" attr.name=\"similarity\" attr.type=\"string\"/>\n",
+            "  <graph id=\"similar\" edgedefault=\"directed\">\n",
+        ));
+        for node in self.nodes.values() {
+            out.push_str(&format!(
+                concat!(
+                    "    <node id=\"{}\">\n",
+                    "      <data key=\"title\">{}</data>\n",
+                    "      <data key=\"authors\">{}</data>\n",
+                    "      <data key=\"depth\">{}</data>\n",
+                    "    </node>\n",
+                ),
+                escape_xml(&node.asin),
+                escape_xml(node.label()),
+                escape_xml(&node.authors.join(", ")),
+                node.depth
+            ));
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                concat!(
+                    "    <edge source=\"{}\" target=\"{}\">\n",
+                    "      <data key=\"similarity\">{}</data>\n",
+                    "    </edge>\n",
+                ),
+                escape_xml(&edge.from),
+                escape_xml(&edge.to),
+                escape_xml(&edge.similarity)
+            ));
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+/// Breadth first explorer of [`Client::get_similar`]
+#[derive(Debug)]
+pub struct Explorer<'a> {
+    client: &'a Client,
+    similarity_types: Vec<SimilarityType>,
+    max_depth: usize,
+    max_nodes: usize,
+    delay: Duration,
+    next_request: Option<Instant>,
+    cache: HashMap<(String, SimilarityType), Vec<Product>>,
+}
+
+impl<'a> Explorer<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            similarity_types: vec![
+                SimilarityType::ByTheSameAuthor,
+                SimilarityType::ByTheSameNarrator,
+                SimilarityType::InTheSameSeries,
+                SimilarityType::RawSimilarities,
+            ],
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+            delay: DEFAULT_DELAY,
+            next_request: None,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn similarity_types(mut self, similarity_types: &[SimilarityType]) -> Self {
+        self.similarity_types = similarity_types.to_vec();
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Products in the graph at most, the starting product included
+    pub fn max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes.max(1);
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// The graph of the products up to `max_depth` hops from `asin`.
+    ///
+    /// Lookups are cached across calls, so exploring from another product of the same graph
+    /// only requests what wasn't looked up before.
+    pub async fn explore(&mut self, asin: &str) -> Result<SimilarGraph> {
+        self.wait().await;
+        let params = json! {{
+            "response_groups": join(NODE_RESPONSE_GROUPS),
+        }};
+        let root = self.client.get_product(asin, Some(params)).await?;
+        let similarity_types = self.similarity_types.clone();
+        let (max_depth, max_nodes) = (self.max_depth, self.max_nodes);
+        bfs(
+            &root,
+            &similarity_types,
+            max_depth,
+            max_nodes,
+            async |asin: &str, similarity_type| self.similar(asin, similarity_type).await,
+        )
+        .await
+    }
+
+    async fn similar(
+        &mut self,
+        asin: &str,
+        similarity_type: SimilarityType,
+    ) -> Result<Vec<Product>> {
+        let key = (asin.to_string(), similarity_type);
+        if let Some(products) = self.cache.get(&key) {
+            return Ok(products.clone());
+        }
+        self.wait().await;
+        let products = self
+            .client
+            .get_similar(asin, similarity_type, NODE_RESPONSE_GROUPS)
+            .await?;
+        self.cache.insert(key, products.clone());
+        Ok(products)
+    }
+
+    /// Wait out the delay since the previous request
+    async fn wait(&mut self) {
+        if let Some(next_request) = self.next_request {
+            sleep_until(next_request).await;
+        }
+        self.next_request = Some(Instant::now() + self.delay);
+    }
+}
+
+async fn bfs(
+    root: &Product,
+    similarity_types: &[SimilarityType],
+    max_depth: usize,
+    max_nodes: usize,
+    mut similar: impl AsyncFnMut(&str, SimilarityType) -> Result<Vec<Product>>,
+) -> Result<SimilarGraph> {
+    let mut graph = SimilarGraph {
+        root: root.asin.clone(),
+        ..Default::default()
+    };
+    graph.nodes.insert(root.asin.clone(), Node::new(root, 0));
+    let mut queue = VecDeque::from([(root.asin.clone(), 0)]);
+
+    while let Some((asin, depth)) = queue.pop_front() {
+        if depth >= max_depth {
+            continue;
+        }
+        for &similarity_type in similarity_types {
+            for product in similar(&asin, similarity_type).await? {
+                if product.asin == asin {
+                    continue;
+                }
+                if !graph.nodes.contains_key(&product.asin) {
+                    if graph.nodes.len() >= max_nodes {
+                        continue;
+                    }
+                    graph
+                        .nodes
+                        .insert(product.asin.clone(), Node::new(&product, depth + 1));
+                    queue.push_back((product.asin.clone(), depth + 1));
+                }
+                graph.edges.insert(Edge {
+                    from: asin.clone(),
+                    to: product.asin,
+                    similarity: format!("{:?}", similarity_type),
+                });
+            }
+        }
+    }
+    Ok(graph)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(asin: &str) -> Product {
+        Product {
+            asin: asin.into(),
+            title: Some(format!("Title \"{}\" & more", asin)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explore() {
+        // A -> B -> C -> D, with B also linking back to A
+        let links: HashMap<&str, Vec<&str>> =
+            [("A", vec!["B"]), ("B", vec!["A", "C"]), ("C", vec!["D"])].into();
+        let mut lookups = 0;
+        let graph = bfs(
+            &product("A"),
+            &[SimilarityType::ByTheSameAuthor],
+            2,
+            10,
+            async |asin: &str, _| {
+                lookups += 1;
+                let linked = links.get(asin).cloned().unwrap_or_default();
+                Ok(linked.into_iter().map(product).collect())
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(lookups, 2);
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), ["A", "B", "C"]);
+        assert_eq!(graph.nodes["C"].depth, 2);
+        assert_eq!(graph.edges.len(), 3);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("  \"A\" [label=\"Title \\\"A\\\" & more\", shape=box];\n"));
+        assert!(dot.contains("  \"B\" -> \"A\" [label=\"ByTheSameAuthor\"];\n"));
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains("<data key=\"title\">Title &quot;C&quot; &amp; more</data>"));
+        assert!(graphml.contains("<edge source=\"B\" target=\"C\">"));
+        let json: SimilarGraph = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+        assert_eq!(json, graph);
+    }
+}
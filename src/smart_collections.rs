@@ -0,0 +1,318 @@
+//! Collections kept in sync with rules over library fields
+//!
+//! Rules can be built in code or declared as JSON, e.g.
+//! `{"all": [{"author": "Brandon Sanderson"}, {"finished": false}]}`.
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::collections::Collection;
+use crate::api::library::LibraryItem;
+use crate::api::response_groups::{join, LibraryResponseGroup};
+use crate::api::Client;
+use crate::Result;
+
+/// Response groups needed to evaluate every rule
+pub const SMART_RESPONSE_GROUPS: &[LibraryResponseGroup] = &[
+    LibraryResponseGroup::Contributors,
+    LibraryResponseGroup::ProductAttrs,
+    LibraryResponseGroup::Series,
+    LibraryResponseGroup::CategoryLadders,
+    LibraryResponseGroup::IsFinished,
+    LibraryResponseGroup::ListeningStatus,
+    LibraryResponseGroup::PercentComplete,
+];
+
+/// A condition on a library item. Text comparisons ignore case
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Author(String),
+    Narrator(String),
+    /// Title of any of the series the item is part of
+    Series(String),
+    /// Any category of the item, e.g. `True Crime`
+    Genre(String),
+    /// Part of the title
+    TitleContains(String),
+    Language(String),
+    Finished(bool),
+    /// Runtime strictly less than the minutes
+    RuntimeUnder(u64),
+    /// Runtime of at least the minutes
+    RuntimeAtLeast(u64),
+    /// Released on or after the date, `YYYY-MM-DD`
+    ReleasedAfter(String),
+    /// Purchased on or after the date, `YYYY-MM-DD`
+    PurchasedAfter(String),
+    All(Vec<Rule>),
+    Any(Vec<Rule>),
+    Not(Box<Rule>),
+}
+
+impl Rule {
+    pub fn matches(&self, item: &LibraryItem) -> bool {
+        let product = &item.product;
+        let eq = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+        let on_or_after = |date: Option<&str>, after: &str| {
+            date.and_then(|d| d.get(..10))
+                .is_some_and(|d| d >= after.trim())
+        };
+        match self {
+            Rule::Author(name) => product.authors.iter().any(|a| eq(&a.name, name)),
+            Rule::Narrator(name) => product.narrators.iter().any(|n| eq(&n.name, name)),
+            Rule::Series(title) => product.series.iter().any(|s| eq(&s.title, title)),
+            Rule::Genre(genre) => product
+                .category_ladders
+                .iter()
+                .flat_map(|l| &l.ladder)
+                .any(|c| eq(&c.name, genre)),
+            Rule::TitleContains(text) => product
+                .title
+                .as_deref()
+                .is_some_and(|t| t.to_lowercase().contains(&text.to_lowercase())),
+            Rule::Language(language) => {
+                product.language.as_deref().is_some_and(|l| eq(l, language))
+            }
+            Rule::Finished(finished) => item.finished() == *finished,
+            Rule::RuntimeUnder(minutes) => product.runtime_length_min.is_some_and(|r| r < *minutes),
+            Rule::RuntimeAtLeast(minutes) => {
+                product.runtime_length_min.is_some_and(|r| r >= *minutes)
+            }
+            Rule::ReleasedAfter(date) => on_or_after(product.release_date.as_deref(), date),
+            Rule::PurchasedAfter(date) => on_or_after(item.purchase_date.as_deref(), date),
+            Rule::All(rules) => rules.iter().all(|r| r.matches(item)),
+            Rule::Any(rules) => rules.iter().any(|r| r.matches(item)),
+            Rule::Not(rule) => !rule.matches(item),
+        }
+    }
+}
+
+/// A collection whose items are the library items matching `rule`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SmartCollection {
+    /// Name of the Audible collection, created when it doesn't exist yet
+    pub name: String,
+    pub description: Option<String>,
+    pub rule: Rule,
+}
+
+/// Changes needed to bring a collection in line with its rule
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CollectionDiff {
+    pub name: String,
+    /// `None` when the collection has to be created
+    pub collection_id: Option<String>,
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+}
+
+impl CollectionDiff {
+    pub fn is_empty(&self) -> bool {
+        self.collection_id.is_some() && self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// Asins of `library` matching the rule, in library order
+pub fn evaluate<'a>(rule: &Rule, library: &'a [LibraryItem]) -> Vec<&'a str> {
+    library
+        .iter()
+        .filter(|item| rule.matches(item))
+        .map(|item| item.product.asin.as_str())
+        .collect()
+}
+
+/// The asins to add to and remove from `current` to get `wanted`
+pub fn diff(wanted: &[&str], current: &[&str]) -> (Vec<String>, Vec<String>) {
+    let wanted_set: HashSet<&str> = wanted.iter().copied().collect();
+    let current_set: HashSet<&str> = current.iter().copied().collect();
+    let add = wanted
+        .iter()
+        .filter(|asin| !current_set.contains(*asin))
+        .map(|asin| asin.to_string())
+        .collect();
+    let remove = current
+        .iter()
+        .filter(|asin| !wanted_set.contains(*asin))
+        .map(|asin| asin.to_string())
+        .collect();
+    (add, remove)
+}
+
+/// Work out the changes to every smart collection, and apply them unless `dry_run`
+pub async fn sync(
+    client: &Client,
+    smart_collections: &[SmartCollection],
+    dry_run: bool,
+) -> Result<Vec<CollectionDiff>> {
+    let params = json! {{
+        "response_groups": join(SMART_RESPONSE_GROUPS),
+    }};
+    let library = client.get_library_items(Some(params)).await?;
+    let existing = client.list_collections(&[]).await?;
+
+    let mut diffs = Vec::with_capacity(smart_collections.len());
+    for smart in smart_collections {
+        let diff = plan(client, smart, &library, &existing).await?;
+        if !dry_run {
+            apply(client, smart, &diff).await?;
+        }
+        diffs.push(diff);
+    }
+    Ok(diffs)
+}
+
+async fn plan(
+    client: &Client,
+    smart: &SmartCollection,
+    library: &[LibraryItem],
+    existing: &[Collection],
+) -> Result<CollectionDiff> {
+    let wanted = evaluate(&smart.rule, library);
+    let collection = existing
+        .iter()
+        .find(|c| c.name.as_deref() == Some(smart.name.as_str()));
+
+    let current = match collection {
+        Some(collection) => {
+            client
+                .get_collection_items(&collection.collection_id)
+                .await?
+        }
+        None => Vec::new(),
+    };
+    let current: Vec<&str> = current.iter().map(|i| i.asin.as_str()).collect();
+    let (add, remove) = diff(&wanted, &current);
+    Ok(CollectionDiff {
+        name: smart.name.clone(),
+        collection_id: collection.map(|c| c.collection_id.clone()),
+        add,
+        remove,
+    })
+}
+
+/// A request needed to apply a diff
+#[derive(Debug, PartialEq)]
+enum Write<'a> {
+    Create(Vec<&'a str>),
+    Add(&'a str, Vec<&'a str>),
+    Remove(&'a str, Vec<&'a str>),
+}
+
+/// The requests applying the diff, none for an empty one
+fn writes(diff: &CollectionDiff) -> Vec<Write<'_>> {
+    let add: Vec<&str> = diff.add.iter().map(String::as_str).collect();
+    let remove: Vec<&str> = diff.remove.iter().map(String::as_str).collect();
+    let Some(collection_id) = diff.collection_id.as_deref() else {
+        return vec![Write::Create(add)];
+    };
+    let mut writes = Vec::new();
+    if !add.is_empty() {
+        writes.push(Write::Add(collection_id, add));
+    }
+    if !remove.is_empty() {
+        writes.push(Write::Remove(collection_id, remove));
+    }
+    writes
+}
+
+async fn apply(client: &Client, smart: &SmartCollection, diff: &CollectionDiff) -> Result<()> {
+    for write in writes(diff) {
+        match write {
+            Write::Create(asins) => {
+                client
+                    .create_collection(&smart.name, smart.description.as_deref(), &asins)
+                    .await?;
+            }
+            Write::Add(collection_id, asins) => {
+                client.add_to_collection(collection_id, &asins).await?;
+            }
+            Write::Remove(collection_id, asins) => {
+                client.remove_from_collection(collection_id, &asins).await?
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::product::{CategoryLadder, CategoryRef, Contributor, Product};
+
+    fn item(asin: &str, author: &str, genre: &str, runtime: u64, finished: bool) -> LibraryItem {
+        LibraryItem {
+            product: Product {
+                asin: asin.into(),
+                authors: vec![Contributor {
+                    asin: None,
+                    name: author.into(),
+                }],
+                runtime_length_min: Some(runtime),
+                category_ladders: vec![CategoryLadder {
+                    root: Some("Genres".into()),
+                    ladder: vec![CategoryRef {
+                        id: "1".into(),
+                        name: genre.into(),
+                    }],
+                }],
+                ..Default::default()
+            },
+            is_finished: Some(finished),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let library = [
+            item("A", "Brandon Sanderson", "Fantasy", 2400, false),
+            item("B", "Brandon Sanderson", "Fantasy", 1200, true),
+            item("C", "Someone Else", "True Crime", 420, false),
+            item("D", "Someone Else", "True Crime", 600, false),
+        ];
+        let rule: Rule = serde_json::from_value(json!({
+            "all": [{"author": "brandon sanderson"}, {"finished": false}]
+        }))
+        .unwrap();
+        assert_eq!(evaluate(&rule, &library), ["A"]);
+
+        let rule = Rule::All(vec![
+            Rule::Genre("True Crime".into()),
+            Rule::RuntimeUnder(8 * 60),
+        ]);
+        assert_eq!(evaluate(&rule, &library), ["C"]);
+
+        let rule = Rule::Not(Box::new(Rule::Author("Someone Else".into())));
+        assert_eq!(evaluate(&rule, &library), ["A", "B"]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let (add, remove) = diff(&["A", "B", "C"], &["B", "D"]);
+        assert_eq!(add, ["A", "C"]);
+        assert_eq!(remove, ["D"]);
+    }
+
+    #[test]
+    fn test_writes() {
+        let mut diff = CollectionDiff {
+            name: "Unfinished".into(),
+            collection_id: Some("abc".into()),
+            add: vec!["A".into()],
+            remove: vec![],
+        };
+        assert_eq!(writes(&diff), [Write::Add("abc", vec!["A"])]);
+
+        diff.add.clear();
+        assert!(diff.is_empty());
+        assert!(writes(&diff).is_empty());
+
+        diff.remove = vec!["B".into()];
+        assert_eq!(writes(&diff), [Write::Remove("abc", vec!["B"])]);
+
+        diff.collection_id = None;
+        assert_eq!(writes(&diff), [Write::Create(vec![])]);
+    }
+}
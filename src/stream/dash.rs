@@ -0,0 +1,420 @@
+//! DASH MPD parsing, supporting `SegmentTemplate` (with or without a `SegmentTimeline`),
+//! `SegmentList` and single file representations
+use std::collections::HashMap;
+
+use url::Url;
+
+use super::{Segment, Variant};
+use crate::Result;
+
+/// Owned XML element, so `SegmentTemplate`s can be merged
+#[derive(Debug, Clone, Default)]
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    fn attr_u64(&self, name: &str) -> Option<u64> {
+        self.attr(name).and_then(|v| v.parse().ok())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    /// Resolve the `BaseURL` of this element against `base`
+    fn base_url(&self, base: &Url) -> Result<Url> {
+        match self.child("BaseURL") {
+            Some(element) => Ok(base.join(element.text.trim())?),
+            None => Ok(base.clone()),
+        }
+    }
+}
+
+/// All representations of audio adaptation sets, with their segments
+pub fn parse_mpd(mpd: &str, base: &Url) -> Result<Vec<Variant>> {
+    let root = parse_xml(mpd)?;
+    if root.name != "MPD" {
+        return Err("Not a DASH manifest: missing MPD".into());
+    }
+    let base = root.base_url(base)?;
+    let total_secs = root
+        .attr("mediaPresentationDuration")
+        .map(parse_duration)
+        .transpose()?;
+
+    let mut variants: Vec<Variant> = Vec::new();
+    let mut period_start_secs = 0.0;
+    for period in root.children("Period") {
+        let base = period.base_url(&base)?;
+        if let Some(start) = period.attr("start") {
+            period_start_secs = parse_duration(start)?;
+        }
+        let period_secs = match period.attr("duration") {
+            Some(duration) => parse_duration(duration)?,
+            None => total_secs.unwrap_or_default() - period_start_secs,
+        };
+
+        for adaptation_set in period.children("AdaptationSet") {
+            let is_audio = adaptation_set.attr("contentType") == Some("audio")
+                || adaptation_set
+                    .attr("mimeType")
+                    .is_some_and(|m| m.starts_with("audio"))
+                || adaptation_set
+                    .children("Representation")
+                    .any(|r| r.attr("mimeType").is_some_and(|m| m.starts_with("audio")));
+            if !is_audio {
+                continue;
+            }
+            let base = adaptation_set.base_url(&base)?;
+
+            for representation in adaptation_set.children("Representation") {
+                let base = representation.base_url(&base)?;
+                let id = representation.attr("id").map(String::from);
+                let bandwidth = representation.attr_u64("bandwidth").unwrap_or_default();
+                let template = merge(
+                    adaptation_set.child("SegmentTemplate"),
+                    representation.child("SegmentTemplate"),
+                );
+                let vars = |number: u64, time: u64| TemplateVars {
+                    id: id.as_deref().unwrap_or_default(),
+                    bandwidth,
+                    number,
+                    time,
+                };
+
+                let (init_uri, mut segments) = if let Some(template) = &template {
+                    let init_uri = template
+                        .attr("initialization")
+                        .map(|init| base.join(&fill_template(init, &vars(0, 0))))
+                        .transpose()?
+                        .map(|url| url.to_string());
+                    let media = template
+                        .attr("media")
+                        .ok_or("SegmentTemplate without media")?;
+                    let mut segments = Vec::new();
+                    for (number, time, start_ms, duration_ms) in
+                        template_timings(template, period_secs)
+                    {
+                        segments.push(Segment {
+                            uri: base
+                                .join(&fill_template(media, &vars(number, time)))?
+                                .to_string(),
+                            start_ms,
+                            duration_ms,
+                            byte_range: None,
+                        });
+                    }
+                    (init_uri, segments)
+                } else if let Some(list) = representation
+                    .child("SegmentList")
+                    .or(adaptation_set.child("SegmentList"))
+                {
+                    let timescale = list.attr_u64("timescale").unwrap_or(1).max(1);
+                    let duration_ms =
+                        list.attr_u64("duration").unwrap_or_default() * 1000 / timescale;
+                    let init_uri = list
+                        .child("Initialization")
+                        .and_then(|i| i.attr("sourceURL"))
+                        .map(|uri| base.join(uri))
+                        .transpose()?
+                        .map(|url| url.to_string());
+                    let mut segments = Vec::new();
+                    for (i, url) in list.children("SegmentURL").enumerate() {
+                        segments.push(Segment {
+                            uri: base
+                                .join(url.attr("media").unwrap_or_default())?
+                                .to_string(),
+                            start_ms: i as u64 * duration_ms,
+                            duration_ms,
+                            byte_range: None,
+                        });
+                    }
+                    (init_uri, segments)
+                } else {
+                    let segment = Segment {
+                        uri: base.to_string(),
+                        start_ms: 0,
+                        duration_ms: (period_secs * 1000.0).round() as u64,
+                        byte_range: None,
+                    };
+                    (None, vec![segment])
+                };
+
+                let period_offset_ms = (period_start_secs * 1000.0).round() as u64;
+                for segment in segments.iter_mut() {
+                    segment.start_ms += period_offset_ms;
+                }
+
+                // representations repeated across periods are continued rather than duplicated
+                match variants.iter_mut().find(|v| v.id.is_some() && v.id == id) {
+                    Some(variant) => variant.segments.extend(segments),
+                    None => variants.push(Variant {
+                        id,
+                        bandwidth,
+                        codecs: representation
+                            .attr("codecs")
+                            .or(adaptation_set.attr("codecs"))
+                            .map(String::from),
+                        uri: base.to_string(),
+                        init_uri,
+                        init_byte_range: None,
+                        segments,
+                    }),
+                }
+            }
+        }
+        period_start_secs += period_secs;
+    }
+    Ok(variants)
+}
+
+/// `SegmentTemplate` attributes of the representation override those of the adaptation set
+fn merge(outer: Option<&Element>, inner: Option<&Element>) -> Option<Element> {
+    match (outer, inner) {
+        (None, None) => None,
+        (Some(outer), None) => Some(outer.clone()),
+        (None, Some(inner)) => Some(inner.clone()),
+        (Some(outer), Some(inner)) => {
+            let mut merged = outer.clone();
+            merged.attributes.extend(inner.attributes.clone());
+            if !inner.children.is_empty() {
+                merged.children = inner.children.clone();
+            }
+            Some(merged)
+        }
+    }
+}
+
+/// `(number, time, start_ms, duration_ms)` of every segment of a template
+fn template_timings(template: &Element, period_secs: f64) -> Vec<(u64, u64, u64, u64)> {
+    let timescale = template.attr_u64("timescale").unwrap_or(1).max(1);
+    let mut number = template.attr_u64("startNumber").unwrap_or(1);
+    // media times are shifted by the offset to get times in the period
+    let offset = template.attr_u64("presentationTimeOffset").unwrap_or(0);
+    let to_ms = |t: u64| t.saturating_sub(offset) * 1000 / timescale;
+    let period_ms = (period_secs * 1000.0).round() as u64;
+    let mut timings = Vec::new();
+
+    if let Some(timeline) = template.child("SegmentTimeline") {
+        let mut time = offset;
+        for s in timeline.children("S") {
+            time = s.attr_u64("t").unwrap_or(time);
+            let Some(duration) = s.attr_u64("d").filter(|d| *d > 0) else {
+                continue;
+            };
+            let repeat: i64 = s.attr("r").and_then(|r| r.parse().ok()).unwrap_or(0);
+            let mut i = 0;
+            // a negative repeat count runs until the end of the period
+            while (repeat >= 0 && i <= repeat) || (repeat < 0 && to_ms(time) < period_ms) {
+                timings.push((
+                    number,
+                    time,
+                    to_ms(time),
+                    to_ms(time + duration) - to_ms(time),
+                ));
+                time += duration;
+                number += 1;
+                i += 1;
+            }
+        }
+    } else if let Some(duration) = template.attr_u64("duration").filter(|d| *d > 0) {
+        let mut time = offset;
+        while to_ms(time) < period_ms {
+            let end_ms = to_ms(time + duration).min(period_ms);
+            timings.push((number, time, to_ms(time), end_ms - to_ms(time)));
+            time += duration;
+            number += 1;
+        }
+    }
+    timings
+}
+
+struct TemplateVars<'a> {
+    id: &'a str,
+    bandwidth: u64,
+    number: u64,
+    time: u64,
+}
+
+/// Substitute `$RepresentationID$`, `$Bandwidth$`, `$Number$` and `$Time$`, with optional `%0Nd` widths
+fn fill_template(template: &str, vars: &TemplateVars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut parts = template.split('$');
+    if let Some(first) = parts.next() {
+        out.push_str(first);
+    }
+    let mut in_identifier = true;
+    for part in parts {
+        if !in_identifier {
+            out.push_str(part);
+            in_identifier = true;
+            continue;
+        }
+        in_identifier = false;
+        let (name, width) = match part.split_once('%') {
+            Some((name, format)) => (
+                name,
+                format
+                    .trim_start_matches('0')
+                    .trim_end_matches('d')
+                    .parse()
+                    .unwrap_or(0),
+            ),
+            None => (part, 0),
+        };
+        match name {
+            "" => out.push('$'),
+            "RepresentationID" => out.push_str(vars.id),
+            "Bandwidth" => out.push_str(&format!("{:0width$}", vars.bandwidth, width = width)),
+            "Number" => out.push_str(&format!("{:0width$}", vars.number, width = width)),
+            "Time" => out.push_str(&format!("{:0width$}", vars.time, width = width)),
+            other => {
+                out.push('$');
+                out.push_str(other);
+                out.push('$');
+            }
+        }
+    }
+    out
+}
+
+/// ISO 8601 duration in seconds, e.g. `PT1H2M3.5S`
+fn parse_duration(duration: &str) -> Result<f64> {
+    let rest = duration
+        .strip_prefix('P')
+        .ok_or_else(|| format!("Invalid duration: {}", duration))?;
+    let mut secs = 0.0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            c if c.is_ascii_digit() || c == '.' => number.push(c),
+            unit => {
+                let value: f64 = number.parse()?;
+                number.clear();
+                secs += value
+                    * match (unit, in_time) {
+                        // years and months have no fixed length
+                        ('Y', false) | ('M', false) => {
+                            return Err(format!("Unsupported duration: {}", duration).into())
+                        }
+                        ('D', false) => 86400.0,
+                        ('H', true) => 3600.0,
+                        ('M', true) => 60.0,
+                        ('S', true) => 1.0,
+                        _ => return Err(format!("Unsupported duration: {}", duration).into()),
+                    };
+            }
+        }
+    }
+    if !number.is_empty() {
+        return Err(format!("Missing unit in duration: {}", duration).into());
+    }
+    Ok(secs)
+}
+
+/// Element tree of the document, namespace prefixes are dropped from element and attribute names
+fn parse_xml(xml: &str) -> Result<Element> {
+    fn element(node: roxmltree::Node) -> Element {
+        Element {
+            name: node.tag_name().name().to_string(),
+            attributes: node
+                .attributes()
+                .map(|a| (a.name().to_string(), a.value().to_string()))
+                .collect(),
+            children: node
+                .children()
+                .filter(|c| c.is_element())
+                .map(element)
+                .collect(),
+            text: node.children().filter_map(|c| c.text()).collect(),
+        }
+    }
+    let document = roxmltree::Document::parse(xml)?;
+    Ok(element(document.root_element()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mpd() {
+        let mpd = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" mediaPresentationDuration="PT25S">
+              <!-- a comment -->
+              <BaseURL>https://cdn.example.com/book/</BaseURL>
+              <Period>
+                <AdaptationSet contentType="audio" mimeType="audio/mp4">
+                  <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%05d$.m4s" startNumber="1">
+                    <SegmentTimeline>
+                      <S t="0" d="10000" r="1"/>
+                      <S d="5000"/>
+                    </SegmentTimeline>
+                  </SegmentTemplate>
+                  <Representation id="aac_128" bandwidth="128000" codecs="mp4a.40.2"/>
+                  <Representation id="aac_64" bandwidth="64000" codecs="mp4a.40.2"/>
+                </AdaptationSet>
+              </Period>
+            </MPD>"#;
+        let base = Url::parse("https://cdn.example.com/manifest.mpd").unwrap();
+        let variants = parse_mpd(mpd, &base).unwrap();
+        assert_eq!(variants.len(), 2);
+        let variant = &variants[0];
+        assert_eq!(variant.bandwidth, 128000);
+        assert_eq!(
+            variant.init_uri.as_deref(),
+            Some("https://cdn.example.com/book/aac_128/init.mp4")
+        );
+        assert_eq!(variant.segments.len(), 3);
+        assert_eq!(
+            variant.segments[2].uri,
+            "https://cdn.example.com/book/aac_128/seg-00003.m4s"
+        );
+        assert_eq!(variant.segments[2].start_ms, 20_000);
+        assert_eq!(variant.duration_ms(), 25_000);
+    }
+
+    #[test]
+    fn test_duration_template() {
+        let mpd = r#"<MPD mediaPresentationDuration="PT0H0M21.5S"><Period><AdaptationSet mimeType="audio/mp4">
+            <Representation id="a" bandwidth="1"><SegmentTemplate duration="10" media="$Time$.m4s"/></Representation>
+            </AdaptationSet></Period></MPD>"#;
+        let base = Url::parse("https://cdn.example.com/").unwrap();
+        let variants = parse_mpd(mpd, &base).unwrap();
+        let segments = &variants[0].segments;
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[2].uri, "https://cdn.example.com/20.m4s");
+        assert_eq!(segments[2].duration_ms, 1_500);
+
+        assert_eq!(parse_duration("P1DT1H0.5S").unwrap(), 90_000.5);
+        assert!(parse_duration("P1Y").is_err());
+        assert!(parse_duration("P2M").is_err());
+        assert!(parse_duration("PT5").is_err());
+
+        let mpd = r#"<MPD mediaPresentationDuration="PT20S"><Period><AdaptationSet mimeType="audio/mp4">
+            <Representation id="a" bandwidth="1"><SegmentTemplate timescale="10" presentationTimeOffset="900" media="$Time$.m4s?a=1&amp;b=2">
+            <SegmentTimeline><S t="900" d="100" r="-1"/></SegmentTimeline></SegmentTemplate></Representation>
+            </AdaptationSet></Period></MPD>"#;
+        let segments = &parse_mpd(mpd, &base).unwrap()[0].segments;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].uri, "https://cdn.example.com/1000.m4s?a=1&b=2");
+        assert_eq!(segments[1].start_ms, 10_000);
+
+        assert!(parse_mpd("<MPD a=é/>", &base).is_err());
+        assert!(parse_mpd("<MPD a=\"é/>", &base).is_err());
+    }
+}
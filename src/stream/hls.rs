@@ -0,0 +1,202 @@
+//! HLS master and media playlist parsing
+use std::collections::HashMap;
+
+use url::Url;
+
+use super::{ByteRange, Segment, Variant};
+use crate::Result;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaPlaylist {
+    pub target_duration: Option<u64>,
+    pub media_sequence: u64,
+    pub init_uri: Option<String>,
+    pub init_byte_range: Option<ByteRange>,
+    pub segments: Vec<Segment>,
+}
+
+pub fn is_master(playlist: &str) -> bool {
+    playlist.contains("#EXT-X-STREAM-INF")
+}
+
+/// Variants of a master playlist, without their segments
+pub fn parse_master(playlist: &str, base: &Url) -> Result<Vec<Variant>> {
+    check_header(playlist)?;
+
+    let mut variants = Vec::new();
+    let mut pending: Option<HashMap<String, String>> = None;
+    for line in playlist.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(parse_attributes(attributes));
+        } else if !line.starts_with('#') {
+            if let Some(attributes) = pending.take() {
+                let bandwidth = attributes
+                    .get("AVERAGE-BANDWIDTH")
+                    .or(attributes.get("BANDWIDTH"))
+                    .and_then(|b| b.parse().ok())
+                    .unwrap_or_default();
+                variants.push(Variant {
+                    id: attributes.get("NAME").cloned(),
+                    bandwidth,
+                    codecs: attributes.get("CODECS").cloned(),
+                    uri: base.join(line)?.to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    Ok(variants)
+}
+
+pub fn parse_media(playlist: &str, base: &Url) -> Result<MediaPlaylist> {
+    check_header(playlist)?;
+
+    let mut media = MediaPlaylist::default();
+    let mut start_secs = 0.0;
+    let mut duration: Option<f64> = None;
+    let mut byte_range: Option<(u64, Option<u64>)> = None;
+    let mut next_offset = 0;
+
+    for line in playlist.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            media.target_duration = value.parse().ok();
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media.media_sequence = value.parse().unwrap_or_default();
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MAP:") {
+            let attributes = parse_attributes(attributes);
+            if let Some(uri) = attributes.get("URI") {
+                media.init_uri = Some(base.join(uri)?.to_string());
+            }
+            // unlike #EXT-X-BYTERANGE, a missing offset here means the start of the file
+            media.init_byte_range = attributes
+                .get("BYTERANGE")
+                .map(|range| parse_byte_range(range))
+                .transpose()?
+                .map(|(length, offset)| ByteRange {
+                    offset: offset.unwrap_or(0),
+                    length,
+                });
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let value = value.split(',').next().unwrap_or_default();
+            duration = Some(value.trim().parse()?);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            byte_range = Some(parse_byte_range(value)?);
+        } else if !line.starts_with('#') {
+            let Some(duration_secs) = duration.take() else {
+                return Err(format!("Segment without #EXTINF: {}", line).into());
+            };
+            // without an explicit offset the range follows the previous one
+            let byte_range = byte_range.take().map(|(length, offset)| {
+                let offset = offset.unwrap_or(next_offset);
+                next_offset = offset + length;
+                ByteRange { offset, length }
+            });
+            let start_ms = (start_secs * 1000.0_f64).round() as u64;
+            start_secs += duration_secs;
+            let end_ms = (start_secs * 1000.0_f64).round() as u64;
+            media.segments.push(Segment {
+                uri: base.join(line)?.to_string(),
+                start_ms,
+                duration_ms: end_ms - start_ms,
+                byte_range,
+            });
+        }
+    }
+    Ok(media)
+}
+
+fn check_header(playlist: &str) -> Result<()> {
+    match playlist.trim_start().starts_with("#EXTM3U") {
+        true => Ok(()),
+        false => Err("Not an HLS playlist: missing #EXTM3U".into()),
+    }
+}
+
+/// `length[@offset]`
+fn parse_byte_range(range: &str) -> Result<(u64, Option<u64>)> {
+    match range.split_once('@') {
+        Some((length, offset)) => Ok((length.parse()?, Some(offset.parse()?))),
+        None => Ok((range.parse()?, None)),
+    }
+}
+
+/// `KEY=value,KEY="quoted, value"`
+fn parse_attributes(attributes: &str) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    let mut rest = attributes.trim();
+    while !rest.is_empty() {
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, after)) => (value, after),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        parsed.insert(key.trim().to_string(), value.to_string());
+        rest = after.trim_start_matches(',').trim();
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_master() {
+        let playlist = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"\n\
+            low/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=128000,AVERAGE-BANDWIDTH=120000,CODECS=\"mp4a.40.2,ec-3\"\n\
+            https://cdn.example.com/high/index.m3u8\n";
+        let base = Url::parse("https://cdn.example.com/book/master.m3u8?token=abc").unwrap();
+        let variants = parse_master(playlist, &base).unwrap();
+        assert_eq!(variants.len(), 2);
+        assert_eq!(
+            variants[0].uri,
+            "https://cdn.example.com/book/low/index.m3u8"
+        );
+        assert_eq!(variants[1].bandwidth, 120000);
+        assert_eq!(variants[1].codecs.as_deref(), Some("mp4a.40.2,ec-3"));
+    }
+
+    #[test]
+    fn test_parse_media() {
+        let playlist = "#EXTM3U\n\
+            #EXT-X-TARGETDURATION:10\n\
+            #EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n\
+            #EXTINF:10.0,\n\
+            #EXT-X-BYTERANGE:1000@0\n\
+            audio.mp4\n\
+            #EXTINF:9.5,\n\
+            #EXT-X-BYTERANGE:800\n\
+            audio.mp4\n\
+            #EXT-X-ENDLIST\n";
+        let base = Url::parse("https://cdn.example.com/book/index.m3u8").unwrap();
+        let media = parse_media(playlist, &base).unwrap();
+        assert_eq!(media.target_duration, Some(10));
+        assert_eq!(
+            media.init_uri.as_deref(),
+            Some("https://cdn.example.com/book/init.mp4")
+        );
+        assert_eq!(
+            media.init_byte_range,
+            Some(ByteRange {
+                offset: 0,
+                length: 720
+            })
+        );
+        assert_eq!(media.segments[1].start_ms, 10_000);
+        assert_eq!(media.segments[1].duration_ms, 9_500);
+        assert_eq!(
+            media.segments[1].byte_range,
+            Some(ByteRange {
+                offset: 1000,
+                length: 800
+            })
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Streaming playback: request a streaming license and resolve its HLS or DASH manifest
+//! into variants, segment urls and chapter aligned seek offsets.
+use serde_json::json;
+use url::Url;
+
+use crate::api::content::Chapter;
+use crate::api::response_groups::{join, ContentResponseGroup};
+use crate::api::Client;
+use crate::Result;
+
+pub mod dash;
+pub mod hls;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Hls,
+    HlsCmaf,
+    Dash,
+}
+
+impl StreamFormat {
+    /// Value used in `supported_media_features.drm_types`
+    pub fn drm_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Hls => "Hls",
+            StreamFormat::HlsCmaf => "HlsCmaf",
+            StreamFormat::Dash => "Dash",
+        }
+    }
+}
+
+/// One rendition of the audio, e.g. a bitrate
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Variant {
+    pub id: Option<String>,
+    /// Bits per second
+    pub bandwidth: u64,
+    pub codecs: Option<String>,
+    /// Media playlist for HLS, the representation's base url for DASH
+    pub uri: String,
+    /// Initialization segment (CMAF/fMP4)
+    pub init_uri: Option<String>,
+    /// Range of `init_uri` holding the initialization segment, the whole file when `None`
+    pub init_byte_range: Option<ByteRange>,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Segment {
+    pub uri: String,
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub byte_range: Option<ByteRange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Where playback of a position starts: the segment to fetch and how far into it to skip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekPoint {
+    pub segment_index: usize,
+    pub offset_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterSeek {
+    pub title: String,
+    pub start_ms: u64,
+    pub seek: SeekPoint,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stream {
+    pub asin: String,
+    pub acr: Option<String>,
+    pub format: StreamFormat,
+    pub manifest_url: String,
+    pub variants: Vec<Variant>,
+    pub chapters: Vec<Chapter>,
+}
+
+impl Variant {
+    pub fn duration_ms(&self) -> u64 {
+        self.segments
+            .last()
+            .map(|s| s.start_ms + s.duration_ms)
+            .unwrap_or_default()
+    }
+
+    /// The segment containing `position_ms`
+    pub fn seek(&self, position_ms: u64) -> Option<SeekPoint> {
+        let index = self
+            .segments
+            .partition_point(|s| s.start_ms + s.duration_ms <= position_ms);
+        let segment = self.segments.get(index)?;
+        Some(SeekPoint {
+            segment_index: index,
+            offset_ms: position_ms.saturating_sub(segment.start_ms),
+        })
+    }
+}
+
+impl Stream {
+    /// The variant with the highest bandwidth
+    pub fn best_variant(&self) -> Option<&Variant> {
+        self.variants.iter().max_by_key(|v| v.bandwidth)
+    }
+
+    /// Seek points for the start of every chapter within `variant`
+    pub fn chapter_seeks(&self, variant: &Variant) -> Vec<ChapterSeek> {
+        self.chapters
+            .iter()
+            .filter_map(|chapter| {
+                Some(ChapterSeek {
+                    title: chapter.title.clone(),
+                    start_ms: chapter.start_offset_ms,
+                    seek: variant.seek(chapter.start_offset_ms)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Request a streaming license for `asin` and resolve its manifest, including the segments of every variant
+pub async fn open_stream(client: &Client, asin: &str, format: StreamFormat) -> Result<Stream> {
+    let params = json! {{
+        "consumption_type": "Streaming",
+        "quality": "High",
+        "use_adaptive_bit_rate": true,
+        "chapter_titles_type": "Flat",
+        "response_groups": join(&[
+            ContentResponseGroup::ChapterInfo,
+            ContentResponseGroup::ContentReference,
+            ContentResponseGroup::ContentUrl,
+        ]),
+        "supported_media_features": {
+            "codecs": ["mp4a.40.2", "mp4a.40.42"],
+            "drm_types": [format.drm_type()],
+        },
+    }};
+    let license = client.get_content_license(asin, params).await?;
+    let manifest_url = license
+        .content_metadata
+        .url("streaming_url")
+        .ok_or("License has no content url")?
+        .to_string();
+
+    let http = reqwest::Client::new();
+    let fetch = |url: Url| {
+        let http = http.clone();
+        async move {
+            let res = http.get(url).send().await?.error_for_status()?;
+            Ok::<String, Box<dyn std::error::Error>>(res.text().await?)
+        }
+    };
+
+    let base = Url::parse(&manifest_url)?;
+    let manifest = fetch(base.clone()).await?;
+    let variants = match format {
+        StreamFormat::Dash => dash::parse_mpd(&manifest, &base)?,
+        StreamFormat::Hls | StreamFormat::HlsCmaf if hls::is_master(&manifest) => {
+            let mut variants = hls::parse_master(&manifest, &base)?;
+            for variant in variants.iter_mut() {
+                let url = Url::parse(&variant.uri)?;
+                let playlist = hls::parse_media(&fetch(url.clone()).await?, &url)?;
+                variant.init_uri = playlist.init_uri;
+                variant.init_byte_range = playlist.init_byte_range;
+                variant.segments = playlist.segments;
+            }
+            variants
+        }
+        StreamFormat::Hls | StreamFormat::HlsCmaf => {
+            let playlist = hls::parse_media(&manifest, &base)?;
+            vec![Variant {
+                uri: manifest_url.clone(),
+                init_uri: playlist.init_uri,
+                init_byte_range: playlist.init_byte_range,
+                segments: playlist.segments,
+                ..Default::default()
+            }]
+        }
+    };
+
+    let chapters = license
+        .content_metadata
+        .chapter_info
+        .map(|info| info.flatten().into_iter().cloned().collect())
+        .unwrap_or_default();
+
+    Ok(Stream {
+        asin: asin.to_string(),
+        acr: license.acr,
+        format,
+        manifest_url,
+        variants,
+        chapters,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant() -> Variant {
+        let segments = (0..4)
+            .map(|i| Segment {
+                uri: format!("seg{}.ts", i),
+                start_ms: i * 10_000,
+                duration_ms: 10_000,
+                byte_range: None,
+            })
+            .collect();
+        Variant {
+            segments,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let variant = variant();
+        assert_eq!(variant.duration_ms(), 40_000);
+        assert_eq!(
+            variant.seek(0),
+            Some(SeekPoint {
+                segment_index: 0,
+                offset_ms: 0
+            })
+        );
+        assert_eq!(
+            variant.seek(25_500),
+            Some(SeekPoint {
+                segment_index: 2,
+                offset_ms: 5_500
+            })
+        );
+        assert_eq!(variant.seek(40_000), None);
+    }
+
+    #[test]
+    fn test_chapter_seeks() {
+        let chapter = |title: &str, start_offset_ms| Chapter {
+            title: title.into(),
+            start_offset_ms,
+            ..Default::default()
+        };
+        let stream = Stream {
+            asin: "B00TEST123".into(),
+            acr: None,
+            format: StreamFormat::Hls,
+            manifest_url: String::new(),
+            variants: vec![variant()],
+            chapters: vec![chapter("Intro", 0), chapter("Chapter 1", 12_000)],
+        };
+        let seeks = stream.chapter_seeks(stream.best_variant().unwrap());
+        assert_eq!(seeks[1].seek.segment_index, 1);
+        assert_eq!(seeks[1].seek.offset_ms, 2_000);
+    }
+}
@@ -0,0 +1,207 @@
+//! Embed book metadata and chapters into downloaded M4B files
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::api::content::{Chapter, ChapterInfo};
+use crate::api::product::Product;
+use crate::api::response_groups::{join, LibraryResponseGroup};
+use crate::api::Client;
+use crate::Result;
+
+mod mp4;
+
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// The tags written into the MP4 container
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub asin: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub authors: Vec<String>,
+    pub narrators: Vec<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub release_date: Option<String>,
+    pub genre: Option<String>,
+    pub series: Option<String>,
+    pub series_sequence: Option<String>,
+    pub copyright: Option<String>,
+    /// JPEG or PNG image data
+    pub cover: Option<Vec<u8>>,
+    pub chapters: Vec<Chapter>,
+}
+
+impl Metadata {
+    pub fn new(product: &Product, chapter_info: &ChapterInfo) -> Self {
+        let series = product.series.first();
+        let description = product
+            .publisher_summary
+            .as_deref()
+            .or(product.merchandising_summary.as_deref())
+            .map(strip_html);
+        let chapters = chapter_info
+            .flatten()
+            .into_iter()
+            .map(|chapter| Chapter {
+                chapters: Vec::new(),
+                ..chapter.clone()
+            })
+            .collect();
+
+        Self {
+            asin: product.asin.clone(),
+            title: product.title.clone().unwrap_or_default(),
+            subtitle: product.subtitle.clone(),
+            authors: product
+                .author_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            narrators: product
+                .narrator_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            publisher: product.publisher_name.clone(),
+            description,
+            release_date: product.release_date.clone(),
+            genre: product.genre().map(String::from),
+            series: series.map(|s| s.title.clone()),
+            series_sequence: series.and_then(|s| s.sequence.clone()),
+            copyright: product.copyright.clone(),
+            cover: None,
+            chapters,
+        }
+    }
+
+    fn items(&self) -> Vec<mp4::Atom> {
+        let mut items = vec![
+            mp4::text_item(b"\xa9nam", &self.title),
+            mp4::text_item(b"\xa9alb", &self.title),
+            // media kind: audiobook
+            mp4::integer_item(b"stik", &[2]),
+            mp4::freeform_item(ITUNES_MEAN, "ASIN", &self.asin),
+        ];
+        let mut text = |kind: &[u8; 4], value: Option<&str>| {
+            if let Some(value) = value.filter(|v| !v.is_empty()) {
+                items.push(mp4::text_item(kind, value));
+            }
+        };
+        let authors = self.authors.join(", ");
+        let narrators = self.narrators.join(", ");
+        text(b"\xa9ART", Some(&authors));
+        text(b"aART", Some(&authors));
+        text(b"\xa9nrt", Some(&narrators));
+        // most players show the composer field as the narrator
+        text(b"\xa9wrt", Some(&narrators));
+        text(b"\xa9st3", self.subtitle.as_deref());
+        text(b"\xa9pub", self.publisher.as_deref());
+        text(b"\xa9day", self.release_date.as_deref());
+        text(b"\xa9gen", self.genre.as_deref());
+        text(b"cprt", self.copyright.as_deref());
+        text(b"ldes", self.description.as_deref());
+        // `desc` is limited to 255 characters
+        let short_description = self
+            .description
+            .as_ref()
+            .map(|d| d.chars().take(255).collect::<String>());
+        text(b"desc", short_description.as_deref());
+        text(b"\xa9mvn", self.series.as_deref());
+
+        if self.series.is_some() {
+            if let Some(sequence) = self
+                .series_sequence
+                .as_ref()
+                .and_then(|s| s.parse::<u16>().ok())
+            {
+                items.push(mp4::integer_item(b"\xa9mvi", &sequence.to_be_bytes()));
+                items.push(mp4::integer_item(b"shwm", &[1]));
+            }
+        }
+        if let Some(cover) = &self.cover {
+            items.push(mp4::cover_item(cover));
+        }
+        items
+    }
+}
+
+/// Gather the metadata, chapters and largest cover of a library item
+pub async fn fetch_metadata(client: &Client, asin: &str) -> Result<Metadata> {
+    let params = json! {{
+        "response_groups": join(&[
+            LibraryResponseGroup::Contributors,
+            LibraryResponseGroup::ProductDesc,
+            LibraryResponseGroup::ProductAttrs,
+            LibraryResponseGroup::ProductExtendedAttrs,
+            LibraryResponseGroup::Series,
+            LibraryResponseGroup::Media,
+            LibraryResponseGroup::CategoryLadders,
+        ]),
+        "image_sizes": "1215,500",
+    }};
+    let item = client.get_library_item(asin, Some(params)).await?;
+    let chapter_info = client.get_chapter_info(asin).await?;
+
+    let mut metadata = Metadata::new(&item.product, &chapter_info);
+    if let Some(url) = item.product.largest_image_url() {
+        let res = reqwest::get(url).await?.error_for_status()?;
+        metadata.cover = Some(res.bytes().await?.to_vec());
+    }
+    Ok(metadata)
+}
+
+/// Write the metadata as iTunes style tags, and the chapters as a Nero chapter list, into the M4B/MP4 at `path`.
+///
+/// Existing tags of the same kind are replaced, all others are kept.
+pub fn tag_file(path: impl AsRef<Path>, metadata: &Metadata) -> Result<()> {
+    let chapters = match metadata.chapters.is_empty() {
+        true => None,
+        false => {
+            let chapters: Vec<_> = metadata
+                .chapters
+                .iter()
+                .map(|c| (c.title.clone(), c.start_offset_ms))
+                .collect();
+            Some(mp4::chapter_list(&chapters)?)
+        }
+    };
+    mp4::write_tags(path.as_ref(), metadata.items(), chapters)
+}
+
+/// Descriptions come as HTML snippets
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    // `&amp;` last, so `&amp;lt;` becomes `&lt;` rather than `<`
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(
+            strip_html("<p>Tom &amp; Jerry write &amp;lt;b&amp;gt; &lt;3</p>"),
+            "Tom & Jerry write &lt;b&gt; <3"
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::Result;
+
+/// Atoms whose payload is made up of child atoms
+const CONTAINERS: [&[u8; 4]; 9] = [
+    b"moov", b"trak", b"mdia", b"minf", b"stbl", b"udta", b"meta", b"ilst", b"edts",
+];
+
+/// In-memory atom tree, only ever used for the (small) `moov` atom
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Atom {
+    pub kind: [u8; 4],
+    /// Leaf payload, or the bytes preceding the children of a container (e.g. version and flags of `meta`)
+    pub data: Vec<u8>,
+    pub children: Option<Vec<Atom>>,
+}
+
+impl Atom {
+    pub fn leaf(kind: &[u8; 4], data: Vec<u8>) -> Self {
+        Self {
+            kind: *kind,
+            data,
+            children: None,
+        }
+    }
+
+    pub fn container(kind: &[u8; 4], data: Vec<u8>, children: Vec<Atom>) -> Self {
+        Self {
+            kind: *kind,
+            data,
+            children: Some(children),
+        }
+    }
+
+    pub fn parse(kind: [u8; 4], payload: &[u8]) -> Result<Self> {
+        Self::parse_as(kind, payload, CONTAINERS.contains(&&kind))
+    }
+
+    fn parse_as(kind: [u8; 4], payload: &[u8], is_container: bool) -> Result<Self> {
+        if !is_container {
+            return Ok(Self::leaf(&kind, payload.to_vec()));
+        }
+        // `meta` is a full box, unless written QuickTime style with the `hdlr` directly inside
+        let prefix = if &kind == b"meta" && payload.get(4..8) != Some(b"hdlr") {
+            4.min(payload.len())
+        } else {
+            0
+        };
+        let mut children = Vec::new();
+        let mut rest = &payload[prefix..];
+        while rest.len() >= 8 {
+            let size = u32::from_be_bytes(rest[0..4].try_into()?) as usize;
+            let child_kind: [u8; 4] = rest[4..8].try_into()?;
+            let (header, size) = match size {
+                0 => (8, rest.len()),
+                1 => {
+                    let size =
+                        u64::from_be_bytes(rest.get(8..16).ok_or("Truncated atom")?.try_into()?);
+                    (16, size as usize)
+                }
+                size => (8, size),
+            };
+            if size < header || size > rest.len() {
+                return Err(format!(
+                    "Invalid size for atom {}",
+                    String::from_utf8_lossy(&child_kind)
+                )
+                .into());
+            }
+            // every metadata item in `ilst` holds a `data` atom
+            let child = match &kind {
+                b"ilst" => Self::parse_as(child_kind, &rest[header..size], true)?,
+                _ => Self::parse(child_kind, &rest[header..size])?,
+            };
+            children.push(child);
+            rest = &rest[size..];
+        }
+        Ok(Self::container(&kind, payload[..prefix].to_vec(), children))
+    }
+
+    pub fn size(&self) -> usize {
+        8 + self.data.len()
+            + self
+                .children
+                .iter()
+                .flatten()
+                .map(|child| child.size())
+                .sum::<usize>()
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) -> Result<()> {
+        let size: u32 = self.size().try_into()?;
+        out.extend_from_slice(&size.to_be_bytes());
+        out.extend_from_slice(&self.kind);
+        out.extend_from_slice(&self.data);
+        for child in self.children.iter().flatten() {
+            child.write_to(out)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the child of the given kind, inserting the one built by `f` if missing
+    pub fn child_or_insert(&mut self, kind: &[u8; 4], f: impl FnOnce() -> Atom) -> &mut Atom {
+        let children = self.children.get_or_insert_with(Vec::new);
+        let index = match children.iter().position(|c| &c.kind == kind) {
+            Some(index) => index,
+            None => {
+                children.push(f());
+                children.len() - 1
+            }
+        };
+        &mut children[index]
+    }
+
+    pub fn remove_children(&mut self, kind: &[u8; 4]) {
+        if let Some(children) = self.children.as_mut() {
+            children.retain(|c| &c.kind != kind);
+        }
+    }
+
+    /// Add `delta` to every chunk offset at or past `from`, in all `stco` and `co64` atoms beneath
+    pub fn shift_chunk_offsets(&mut self, from: u64, delta: i64) -> Result<()> {
+        match &self.kind {
+            b"stco" => {
+                for entry in self
+                    .data
+                    .get_mut(8..)
+                    .unwrap_or_default()
+                    .chunks_exact_mut(4)
+                {
+                    let offset = u32::from_be_bytes(entry.try_into()?) as u64;
+                    if offset >= from {
+                        let shifted: u32 = (offset as i64 + delta).try_into()?;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            }
+            b"co64" => {
+                for entry in self
+                    .data
+                    .get_mut(8..)
+                    .unwrap_or_default()
+                    .chunks_exact_mut(8)
+                {
+                    let offset = u64::from_be_bytes(entry.try_into()?);
+                    if offset >= from {
+                        let shifted = (offset as i64 + delta) as u64;
+                        entry.copy_from_slice(&shifted.to_be_bytes());
+                    }
+                }
+            }
+            _ => {
+                for child in self.children.iter_mut().flatten() {
+                    child.shift_chunk_offsets(from, delta)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `data` atom with the given well-known type (1: UTF-8, 13: JPEG, 14: PNG, 21: integer)
+fn data_atom(data_type: u32, value: &[u8]) -> Atom {
+    let mut data = Vec::with_capacity(8 + value.len());
+    data.extend_from_slice(&data_type.to_be_bytes());
+    data.extend_from_slice(&0u32.to_be_bytes()); // locale
+    data.extend_from_slice(value);
+    Atom::leaf(b"data", data)
+}
+
+pub(crate) fn text_item(kind: &[u8; 4], value: &str) -> Atom {
+    Atom::container(kind, Vec::new(), vec![data_atom(1, value.as_bytes())])
+}
+
+pub(crate) fn integer_item(kind: &[u8; 4], value: &[u8]) -> Atom {
+    Atom::container(kind, Vec::new(), vec![data_atom(21, value)])
+}
+
+pub(crate) fn cover_item(image: &[u8]) -> Atom {
+    let data_type = if image.starts_with(b"\x89PNG") {
+        14
+    } else {
+        13
+    };
+    Atom::container(b"covr", Vec::new(), vec![data_atom(data_type, image)])
+}
+
+/// iTunes freeform `----` item, e.g. `com.apple.iTunes:ASIN`
+pub(crate) fn freeform_item(mean: &str, name: &str, value: &str) -> Atom {
+    let full_box = |kind: &[u8; 4], value: &str| {
+        let mut data = vec![0; 4];
+        data.extend_from_slice(value.as_bytes());
+        Atom::leaf(kind, data)
+    };
+    Atom::container(
+        b"----",
+        Vec::new(),
+        vec![
+            full_box(b"mean", mean),
+            full_box(b"name", name),
+            data_atom(1, value.as_bytes()),
+        ],
+    )
+}
+
+/// Nero `chpl` chapter list, start times are in milliseconds.
+/// The format stores at most 255 chapters, more are an error, and titles are cut to 255 bytes.
+pub(crate) fn chapter_list(chapters: &[(String, u64)]) -> Result<Atom> {
+    let count: u8 = chapters.len().try_into().map_err(|_| {
+        format!(
+            "A chapter list holds at most {} chapters: {}",
+            u8::MAX,
+            chapters.len()
+        )
+    })?;
+    let mut data = vec![1, 0, 0, 0]; // version 1
+    data.extend_from_slice(&[0; 4]);
+    data.push(count);
+    for (title, start_ms) in chapters {
+        // 100 nanosecond units
+        data.extend_from_slice(&(start_ms * 10_000).to_be_bytes());
+        let mut end = title.len().min(u8::MAX as usize);
+        while !title.is_char_boundary(end) {
+            end -= 1;
+        }
+        data.push(end as u8);
+        data.extend_from_slice(&title.as_bytes()[..end]);
+    }
+    Ok(Atom::leaf(b"chpl", data))
+}
+
+fn freeform_name(atom: &Atom) -> Option<&[u8]> {
+    atom.children
+        .iter()
+        .flatten()
+        .find(|c| &c.kind == b"name")
+        .and_then(|name| name.data.get(4..))
+}
+
+/// Merge `items` into the `ilst` of `moov`, and replace its chapter list if given
+pub(crate) fn update_moov(moov: &mut Atom, items: Vec<Atom>, chapters: Option<Atom>) {
+    let udta = moov.child_or_insert(b"udta", || Atom::container(b"udta", Vec::new(), Vec::new()));
+
+    if let Some(chapters) = chapters {
+        udta.remove_children(b"chpl");
+        udta.children.get_or_insert_with(Vec::new).push(chapters);
+    }
+
+    let meta = udta.child_or_insert(b"meta", || {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0; 9]);
+        Atom::container(b"meta", vec![0; 4], vec![Atom::leaf(b"hdlr", hdlr)])
+    });
+    let ilst = meta.child_or_insert(b"ilst", || Atom::container(b"ilst", Vec::new(), Vec::new()));
+    let existing = ilst.children.get_or_insert_with(Vec::new);
+
+    for item in items {
+        existing.retain(|old| {
+            old.kind != item.kind
+                || (&item.kind == b"----" && freeform_name(old) != freeform_name(&item))
+        });
+        existing.push(item);
+    }
+}
+
+struct TopLevelAtom {
+    kind: [u8; 4],
+    offset: u64,
+    /// 8, or 16 when the size is stored as 64 bits
+    header_len: u64,
+    size: u64,
+}
+
+fn top_level_atoms(file: &mut File) -> Result<Vec<TopLevelAtom>> {
+    let len = file.metadata()?.len();
+    let mut atoms = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let kind: [u8; 4] = header[4..8].try_into()?;
+        let (header_len, size) = match u32::from_be_bytes(header[0..4].try_into()?) {
+            0 => (8, len - offset),
+            1 => {
+                let mut large = [0; 8];
+                file.read_exact(&mut large)?;
+                (16, u64::from_be_bytes(large))
+            }
+            size => (8, size as u64),
+        };
+        if size < header_len || offset + size > len {
+            return Err(format!("Invalid size for atom {}", String::from_utf8_lossy(&kind)).into());
+        }
+        atoms.push(TopLevelAtom {
+            kind,
+            offset,
+            header_len,
+            size,
+        });
+        offset += size;
+    }
+    Ok(atoms)
+}
+
+/// Rewrite the `moov` atom of the MP4 at `path` in place with the given metadata items and chapters.
+///
+/// The audio data is streamed through untouched; chunk offsets are patched when `moov` precedes `mdat`.
+pub(crate) fn write_tags(path: &Path, items: Vec<Atom>, chapters: Option<Atom>) -> Result<()> {
+    let mut file = File::open(path)?;
+    let atoms = top_level_atoms(&mut file)?;
+    if !atoms.iter().any(|a| &a.kind == b"ftyp") {
+        return Err("Not an MP4 file: missing ftyp".into());
+    }
+    let moov_atom = atoms
+        .iter()
+        .find(|a| &a.kind == b"moov")
+        .ok_or("Not an MP4 file: missing moov")?;
+
+    let header_len = moov_atom.header_len;
+    let mut payload = vec![0; (moov_atom.size - header_len) as usize];
+    file.seek(SeekFrom::Start(moov_atom.offset + header_len))?;
+    file.read_exact(&mut payload)?;
+    let mut moov = Atom::parse(*b"moov", &payload)?;
+
+    update_moov(&mut moov, items, chapters);
+
+    let moov_end = moov_atom.offset + moov_atom.size;
+    let delta = moov.size() as i64 - moov_atom.size as i64;
+    if delta != 0
+        && atoms
+            .iter()
+            .any(|a| &a.kind == b"mdat" && a.offset > moov_atom.offset)
+    {
+        moov.shift_chunk_offsets(moov_end, delta)?;
+    }
+    let mut new_moov = Vec::with_capacity(moov.size());
+    moov.write_to(&mut new_moov)?;
+
+    let tmp_path = path.with_extension("tagging.tmp");
+    let result = (|| -> Result<()> {
+        let mut out = BufWriter::new(File::create(&tmp_path)?);
+        for atom in &atoms {
+            if atom.offset == moov_atom.offset {
+                out.write_all(&new_moov)?;
+                continue;
+            }
+            file.seek(SeekFrom::Start(atom.offset))?;
+            std::io::copy(&mut (&mut file).take(atom.size), &mut out)?;
+        }
+        out.flush()?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minimal_mp4(audio: &[u8]) -> Vec<u8> {
+        let ftyp = Atom::leaf(b"ftyp", b"M4B \0\0\0\0M4B mp42isom".to_vec());
+        let stco = |offset: u32| {
+            let mut data = vec![0; 4];
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(&offset.to_be_bytes());
+            Atom::leaf(b"stco", data)
+        };
+        let moov = |offset| {
+            let stbl = Atom::container(b"stbl", Vec::new(), vec![stco(offset)]);
+            let minf = Atom::container(b"minf", Vec::new(), vec![stbl]);
+            let mdia = Atom::container(b"mdia", Vec::new(), vec![minf]);
+            let trak = Atom::container(b"trak", Vec::new(), vec![mdia]);
+            Atom::container(b"moov", Vec::new(), vec![trak])
+        };
+        let offset = (ftyp.size() + moov(0).size() + 8) as u32;
+        let mut out = Vec::new();
+        ftyp.write_to(&mut out).unwrap();
+        moov(offset).write_to(&mut out).unwrap();
+        Atom::leaf(b"mdat", audio.to_vec())
+            .write_to(&mut out)
+            .unwrap();
+        out
+    }
+
+    fn find<'a>(atom: &'a Atom, path: &[&[u8; 4]]) -> Option<&'a Atom> {
+        match path.split_first() {
+            None => Some(atom),
+            Some((kind, rest)) => atom
+                .children
+                .iter()
+                .flatten()
+                .find(|c| &&c.kind == kind)
+                .and_then(|c| find(c, rest)),
+        }
+    }
+
+    #[test]
+    fn test_write_tags() {
+        let audio = b"not really aac";
+        let path =
+            std::env::temp_dir().join(format!("audible_api_tagging_{}.m4b", std::process::id()));
+        std::fs::write(&path, minimal_mp4(audio)).unwrap();
+
+        let items = vec![
+            text_item(b"\xa9nam", "Endurance"),
+            cover_item(b"\xff\xd8\xff"),
+        ];
+        let chapters =
+            chapter_list(&[("Opening Credits".into(), 0), ("Chapter 1".into(), 21_000)]).unwrap();
+        let too_many: Vec<(String, u64)> = (0..256).map(|i| (i.to_string(), i)).collect();
+        assert!(chapter_list(&too_many).is_err());
+        write_tags(&path, items, Some(chapters)).unwrap();
+        // tagging twice replaces instead of duplicating
+        write_tags(&path, vec![text_item(b"\xa9nam", "Endurance")], None).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // parse the whole file as if it were the payload of a container
+        let root = Atom::parse(*b"moov", &bytes).unwrap();
+
+        let ilst = find(&root, &[b"moov", b"udta", b"meta", b"ilst"]).unwrap();
+        let titles: Vec<_> = ilst
+            .children
+            .iter()
+            .flatten()
+            .filter(|c| &c.kind == b"\xa9nam")
+            .collect();
+        assert_eq!(titles.len(), 1);
+        assert_eq!(
+            &titles[0].children.as_ref().unwrap()[0].data[8..],
+            b"Endurance"
+        );
+        assert!(find(&root, &[b"moov", b"udta", b"meta", b"ilst", b"covr"]).is_some());
+
+        let chpl = find(&root, &[b"moov", b"udta", b"chpl"]).unwrap();
+        assert_eq!(chpl.data[8], 2);
+
+        let stco = find(
+            &root,
+            &[b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stco"],
+        )
+        .unwrap();
+        let offset = u32::from_be_bytes(stco.data[8..12].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[offset..offset + audio.len()], audio);
+    }
+}
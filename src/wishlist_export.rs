@@ -0,0 +1,251 @@
+//! Import and export the wishlist as CSV or JSON
+//!
+//! Entries keep the title and authors next to the asin, so a wishlist exported from one
+//! marketplace can be imported into another where the asins differ.
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::api::catalog::{CatalogResponseGroup, CatalogSearch};
+use crate::api::product::Product;
+use crate::api::response_groups::{join, WishlistResponseGroup};
+use crate::api::wishlist::{BulkResult, WishlistItem};
+use crate::api::Client;
+use crate::Result;
+
+const CSV_HEADER: [&str; 5] = ["asin", "title", "authors", "narrators", "added_timestamp"];
+/// Separates multiple authors or narrators within a CSV field
+const LIST_SEPARATOR: &str = "; ";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct WishlistEntry {
+    pub asin: String,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub narrators: Vec<String>,
+    pub added_timestamp: Option<String>,
+}
+
+impl From<&WishlistItem> for WishlistEntry {
+    fn from(item: &WishlistItem) -> Self {
+        let product = &item.product;
+        Self {
+            asin: product.asin.clone(),
+            title: product.title.clone(),
+            authors: product
+                .author_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            narrators: product
+                .narrator_names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            added_timestamp: item.added_timestamp.clone(),
+        }
+    }
+}
+
+/// The whole wishlist, with the fields needed for an export
+pub async fn fetch_entries(client: &Client) -> Result<Vec<WishlistEntry>> {
+    let params = json! {{
+        "response_groups": join(&[
+            WishlistResponseGroup::Contributors,
+            WishlistResponseGroup::ProductAttrs,
+        ]),
+    }};
+    let items = client.get_wishlist_items(Some(params)).await?;
+    Ok(items.iter().map(WishlistEntry::from).collect())
+}
+
+pub fn to_json(entries: &[WishlistEntry]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(entries)?)
+}
+
+pub fn from_json(json: &str) -> Result<Vec<WishlistEntry>> {
+    Ok(serde_json::from_str(json)?)
+}
+
+pub fn to_csv(entries: &[WishlistEntry]) -> String {
+    let mut out = CSV_HEADER.join(",");
+    out.push('\n');
+    for entry in entries {
+        let fields = [
+            entry.asin.clone(),
+            entry.title.clone().unwrap_or_default(),
+            entry.authors.join(LIST_SEPARATOR),
+            entry.narrators.join(LIST_SEPARATOR),
+            entry.added_timestamp.clone().unwrap_or_default(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Parse a CSV with a header row, only the `asin` column is required
+pub fn from_csv(csv: &str) -> Result<Vec<WishlistEntry>> {
+    let mut rows = parse_csv(csv)?.into_iter();
+    let header = rows.next().ok_or("Empty CSV")?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let asin_column = column("asin").ok_or("CSV has no asin column")?;
+    let [title, authors, narrators, added] =
+        ["title", "authors", "narrators", "added_timestamp"].map(column);
+
+    let mut entries = Vec::new();
+    for row in rows {
+        let field = |n: Option<usize>| {
+            n.and_then(|n| row.get(n))
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+        };
+        let list = |n: Option<usize>| -> Vec<String> {
+            field(n)
+                .map(|f| f.split(';').map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default()
+        };
+        let Some(asin) = field(Some(asin_column)) else {
+            continue;
+        };
+        entries.push(WishlistEntry {
+            asin,
+            title: field(title),
+            authors: list(authors),
+            narrators: list(narrators),
+            added_timestamp: field(added),
+        });
+    }
+    Ok(entries)
+}
+
+/// Add the entries to the wishlist.
+///
+/// Entries whose asin can't be added, e.g. because it belongs to another marketplace, are
+/// looked up in the catalog by title and author and the match is added instead.
+pub async fn import(
+    client: &Client,
+    entries: &[WishlistEntry],
+    concurrency: usize,
+) -> Result<Vec<BulkResult>> {
+    let asins: Vec<&str> = entries.iter().map(|e| e.asin.as_str()).collect();
+    let mut results = client.add_many_to_wishlist(&asins, concurrency).await;
+
+    for (entry, result) in entries.iter().zip(results.iter_mut()) {
+        if result.is_ok() {
+            continue;
+        }
+        // a failed lookup keeps the original error of the entry
+        if let Ok(Some(product)) = find_in_catalog(client, entry).await {
+            let added = client.add_to_wishlist(&product.asin).await;
+            *result = BulkResult {
+                asin: product.asin,
+                result: added.map_err(|e| e.to_string()),
+            };
+        }
+    }
+    Ok(results)
+}
+
+/// A product of the current marketplace with the same title and first author
+async fn find_in_catalog(client: &Client, entry: &WishlistEntry) -> Result<Option<Product>> {
+    let Some(title) = &entry.title else {
+        return Ok(None);
+    };
+    let mut search = CatalogSearch::new()
+        .title(title)
+        .response_groups(&[
+            CatalogResponseGroup::Contributors,
+            CatalogResponseGroup::ProductAttrs,
+        ])
+        .num_results(10)?;
+    if let Some(author) = entry.authors.first() {
+        search = search.author(author);
+    }
+    let products = client.search_catalog(&search).await?.products;
+    Ok(products.into_iter().find(|product| {
+        product
+            .title
+            .as_deref()
+            .is_some_and(|t| t.eq_ignore_ascii_case(title))
+    }))
+}
+
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+/// RFC 4180 rows, quoted fields may contain commas, quotes and newlines
+fn parse_csv(csv: &str) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field in CSV".into());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_round_trip() {
+        let entries = vec![
+            WishlistEntry {
+                asin: "B00TEST123".into(),
+                title: Some("Hello, \"World\"".into()),
+                authors: vec!["Jane Doe".into(), "John Roe".into()],
+                narrators: vec![],
+                added_timestamp: Some("2024-01-01T00:00:00Z".into()),
+            },
+            WishlistEntry {
+                asin: "B00TEST456".into(),
+                ..Default::default()
+            },
+        ];
+        let csv = to_csv(&entries);
+        assert!(csv.contains("B00TEST123,\"Hello, \"\"World\"\"\",Jane Doe; John Roe,,"));
+        assert_eq!(from_csv(&csv).unwrap(), entries);
+
+        let entries = from_csv("title,ASIN\r\nSome Book,B00TEST789\r\n").unwrap();
+        assert_eq!(entries[0].asin, "B00TEST789");
+        assert_eq!(entries[0].title.as_deref(), Some("Some Book"));
+        assert!(from_csv("title\nSome Book\n").is_err());
+    }
+}